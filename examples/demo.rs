@@ -4,12 +4,14 @@ extern crate travis;
 extern crate hyper;
 
 use std::env;
+use std::time::Duration;
 
 use futures::{Future as StdFuture, Stream as StdStream, future};
 use futures::stream::futures_unordered;
 use hyper::client::Connect;
 use tokio_core::reactor::Core;
 use travis::{Client, Credential, Future, Result, State, builds, repos};
+use travis::rate_limit::Throttle;
 
 fn jobs<C>(state: State, builds: builds::Builds<C>) -> Future<usize>
 where
@@ -19,7 +21,7 @@ where
         builds
             .iter(&builds::ListOptions::builder()
                 .state(state.clone())
-                .include(vec!["build.jobs".into()])
+                .include(vec![builds::Include::Jobs])
                 .build()
                 .unwrap())
             .fold::<_, _, Future<usize>>(0, move |acc, build| {
@@ -28,7 +30,8 @@ where
                         build
                             .jobs
                             .iter()
-                            .filter(|job| Some(state.clone()) == job.state)
+                            .filter_map(|job| job.standard())
+                            .filter(|job| Some(&state) == job.state.as_ref())
                             .count(),
                 ))
             }),
@@ -44,7 +47,9 @@ fn run() -> Result<()> {
         ),
         // core for credential exchange ( if needed )
         &mut core,
-    )?;
+    )?
+        // fan out to at most 10 requests per second
+        .with_throttle(Throttle::new(10, Duration::from_secs(1)));
 
     // all running and pending jobs
     let work = travis
@@ -0,0 +1,512 @@
+//! Synchronous interfaces for programs which don't run a tokio reactor
+//!
+//! A blocking `Client` runs an asynchronous client on its own reactor
+//! thread, which stops once the client and everything obtained from it
+//! are dropped. Paginated streams are exposed as iterators.
+//!
+//! ```no_run
+//! extern crate travis;
+//!
+//! use travis::blocking::Client;
+//! use travis::builds::ListOptions;
+//!
+//! fn main() {
+//!   let travis = Client::oss(None).unwrap();
+//!   let builds = travis.builds("softprops/travis");
+//!   for build in builds.iter(&ListOptions::default()) {
+//!     println!("{:?}", build.map(|build| build.state));
+//!   }
+//! }
+//! ```
+
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use Credential;
+use {Future, Result, SendStream};
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use ClientBuilder;
+use builds::{self, Build};
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use connect::DefaultConnector;
+use env::{EnvVar, EnvVarCreate, EnvVarPatch};
+use futures::{Future as StdFuture, Stream as StdStream};
+use futures::stream::Wait;
+use futures::sync::oneshot;
+use hyper::client::Connect;
+use jobs::Job;
+use pagination::PageOptions;
+use rate_limit::RateLimit;
+use serde::de::DeserializeOwned;
+use remote::RemoteClient;
+use repos::{self, Repository};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::thread;
+use tokio_core::reactor::{Core, Handle};
+
+/// stops the reactor thread when dropped
+struct Reactor {
+    _shutdown: oneshot::Sender<()>,
+}
+
+/// Entry point for synchronous travis operations
+///
+/// Instances of Clients may be cloned, sharing a reactor thread.
+#[derive(Clone)]
+pub struct Client<C>
+where
+    C: Clone + Connect,
+{
+    remote: RemoteClient<C>,
+    reactor: Arc<Reactor>,
+}
+
+impl<C> fmt::Debug for Client<C>
+where
+    C: Clone + Connect,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Client")
+    }
+}
+
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+impl Client<DefaultConnector> {
+    /// Creates a Travis client for open source github repository builds
+    pub fn oss(credential: Option<Credential>) -> Result<Self> {
+        Client::spawn(|handle| ::Client::oss_async(credential, handle))
+    }
+
+    /// Creates a Travis client for private github repository builds
+    pub fn pro(credential: Option<Credential>) -> Result<Self> {
+        Client::spawn(|handle| ::Client::pro_async(credential, handle))
+    }
+
+    /// Creates a Travis client configured by `builder`
+    pub fn from_builder(builder: ClientBuilder) -> Result<Self> {
+        Client::spawn(|handle| builder.build_async(handle))
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Clone + Connect,
+{
+    /// Starts a reactor thread and creates an asynchronous client on it
+    /// with `client`, returning once it's created
+    pub fn spawn<F>(client: F) -> Result<Self>
+    where
+        F: FnOnce(&Handle) -> Future<::Client<C>> + Send + 'static,
+    {
+        let (created, creating) = mpsc::channel();
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        thread::spawn(move || {
+            let mut core = match Core::new() {
+                Ok(core) => core,
+                Err(err) => return created.send(Err(err.into())).unwrap_or(()),
+            };
+            let client = match core.run(client(&core.handle())) {
+                Ok(client) => client,
+                Err(err) => return created.send(Err(err)).unwrap_or(()),
+            };
+            if created.send(Ok(client.into_remote())).is_ok() {
+                let _ = core.run(stopped.then(|_| Ok::<(), ()>(())));
+            }
+        });
+        let remote = creating.recv().expect("reactor thread panicked")?;
+        Ok(Client {
+            remote,
+            reactor: Arc::new(Reactor { _shutdown: shutdown }),
+        })
+    }
+
+    /// A handle to the asynchronous client, for operations without a
+    /// blocking counterpart
+    pub fn remote(&self) -> &RemoteClient<C> {
+        &self.remote
+    }
+
+    /// The rate limit status reported with the most recent response,
+    /// if travis reported one
+    pub fn rate_limit(&self) -> Result<Option<RateLimit>> {
+        self.remote
+            .run(|travis| {
+                Box::new(::futures::future::ok(travis.rate_limit()))
+            })
+            .wait()
+    }
+
+    /// get a list of repos for the a given owner (user or org)
+    pub fn repos(&self) -> Repos<C> {
+        Repos { travis: self.clone() }
+    }
+
+    /// get a ref to an env for a given repo slug
+    pub fn env<S>(&self, slug: S) -> Env<C>
+    where
+        S: Into<String>,
+    {
+        Env {
+            travis: self.clone(),
+            slug: slug.into(),
+        }
+    }
+
+    /// get a ref builds associated with a repo slug
+    pub fn builds<S>(&self, slug: S) -> Builds<C>
+    where
+        S: Into<String>,
+    {
+        Builds {
+            travis: self.clone(),
+            slug: slug.into(),
+        }
+    }
+
+    /// get a ref to jobs associated with a build
+    pub fn jobs(&self, build_id: usize) -> Jobs<C> {
+        Jobs {
+            travis: self.clone(),
+            build_id,
+        }
+    }
+
+    /// Loads the representation linked to by `href`. See `Client::follow`
+    pub fn follow<T>(&self, href: &str) -> Result<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let href = href.to_owned();
+        self.wait(move |travis| travis.follow(&href))
+    }
+
+    /// performs `operation` with the asynchronous client, blocking until
+    /// it completes
+    fn wait<F, T>(&self, operation: F) -> Result<T>
+    where
+        F: FnOnce(&::Client<C>) -> Future<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.remote.run(operation).wait()
+    }
+
+    /// iterates over the items of `operation`
+    fn iter<F, T>(&self, operation: F) -> Iter<T>
+    where
+        F: FnOnce(&::Client<C>) -> ::Stream<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        Iter {
+            items: self.remote.stream(operation).wait(),
+            _reactor: self.reactor.clone(),
+        }
+    }
+}
+
+/// An iterator over the items of a paginated collection, fetching pages
+/// as needed
+pub struct Iter<T> {
+    items: Wait<SendStream<T>>,
+    _reactor: Arc<Reactor>,
+}
+
+impl<T> Iterator for Iter<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        self.items.next()
+    }
+}
+
+impl<T> fmt::Debug for Iter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Iter")
+    }
+}
+
+/// Blocking interface for repositories. See `repos::Repos`
+#[derive(Clone, Debug)]
+pub struct Repos<C>
+where
+    C: Clone + Connect,
+{
+    travis: Client<C>,
+}
+
+impl<C> Repos<C>
+where
+    C: Clone + Connect,
+{
+    /// lists the first page of an owner's (user or org) repositories. See
+    /// `iter` for repositories across pages
+    pub fn list<O>(
+        &self,
+        owner: O,
+        options: &repos::ListOptions,
+    ) -> Result<Vec<Repository>>
+    where
+        O: Into<String>,
+    {
+        let owner = owner.into();
+        let options = options.clone();
+        self.travis.wait(move |travis| {
+            travis.repos().list(owner.as_str(), &options)
+        })
+    }
+
+    /// iterates over an owner's repositories across pages
+    pub fn iter<O>(
+        &self,
+        owner: O,
+        options: &repos::ListOptions,
+    ) -> Iter<Repository>
+    where
+        O: Into<String>,
+    {
+        self.iter_with(owner, options, &PageOptions::default())
+    }
+
+    /// iterates over an owner's repositories, requesting them as described
+    /// by `paging`
+    pub fn iter_with<O>(
+        &self,
+        owner: O,
+        options: &repos::ListOptions,
+        paging: &PageOptions,
+    ) -> Iter<Repository>
+    where
+        O: Into<String>,
+    {
+        let owner = owner.into();
+        let options = options.clone();
+        let paging = paging.clone();
+        self.travis.iter(move |travis| {
+            travis.repos().iter_with(owner, &options, &paging)
+        })
+    }
+}
+
+/// Blocking interface for a repository's builds. See `builds::Builds`
+#[derive(Clone, Debug)]
+pub struct Builds<C>
+where
+    C: Clone + Connect,
+{
+    travis: Client<C>,
+    slug: String,
+}
+
+impl<C> Builds<C>
+where
+    C: Clone + Connect,
+{
+    /// lists the first page of builds, as described by `options`. See
+    /// `iter` for builds across pages
+    pub fn list(&self, options: &builds::ListOptions) -> Result<Vec<Build>> {
+        let slug = self.slug.clone();
+        let options = options.clone();
+        self.travis.wait(move |travis| {
+            travis.builds(slug.as_str()).list(&options)
+        })
+    }
+
+    /// iterates over builds across pages
+    pub fn iter(&self, options: &builds::ListOptions) -> Iter<Build> {
+        self.iter_with(options, &PageOptions::default())
+    }
+
+    /// iterates over builds, requesting them as described by `paging`
+    pub fn iter_with(
+        &self,
+        options: &builds::ListOptions,
+        paging: &PageOptions,
+    ) -> Iter<Build> {
+        let slug = self.slug.clone();
+        let options = options.clone();
+        let paging = paging.clone();
+        self.travis.iter(move |travis| {
+            travis.builds(slug.as_str()).iter_with(&options, &paging)
+        })
+    }
+}
+
+/// Blocking interface for a repository's env vars. See `env::Env`
+#[derive(Clone, Debug)]
+pub struct Env<C>
+where
+    C: Clone + Connect,
+{
+    travis: Client<C>,
+    slug: String,
+}
+
+impl<C> Env<C>
+where
+    C: Clone + Connect,
+{
+    /// Return a vector of EnvVars
+    pub fn vars(&self) -> Result<Vec<EnvVar>> {
+        let slug = self.slug.clone();
+        self.travis.wait(move |travis| travis.env(slug.as_str()).vars())
+    }
+
+    /// gets an env var by id
+    pub fn get<V>(&self, var_id: V) -> Result<EnvVar>
+    where
+        V: Into<String>,
+    {
+        let slug = self.slug.clone();
+        let var_id = var_id.into();
+        self.travis.wait(move |travis| {
+            travis.env(slug.as_str()).get(var_id.as_str())
+        })
+    }
+
+    /// updates the contents of an env var
+    pub fn update<V>(&self, var_id: V, options: EnvVarPatch) -> Result<EnvVar>
+    where
+        V: Into<String>,
+    {
+        let slug = self.slug.clone();
+        let var_id = var_id.into();
+        self.travis.wait(move |travis| {
+            travis.env(slug.as_str()).update(var_id.as_str(), options)
+        })
+    }
+
+    /// sets a new env var for this repo
+    pub fn set(&self, options: EnvVarCreate) -> Result<EnvVar> {
+        let slug = self.slug.clone();
+        self.travis.wait(
+            move |travis| travis.env(slug.as_str()).set(options),
+        )
+    }
+
+    /// deletes env var
+    pub fn delete<V>(&self, var_id: V) -> Result<()>
+    where
+        V: Into<String>,
+    {
+        let slug = self.slug.clone();
+        let var_id = var_id.into();
+        self.travis.wait(move |travis| {
+            travis.env(slug.as_str()).delete(var_id.as_str())
+        })
+    }
+}
+
+/// Blocking interface for a build's jobs. See `jobs::Jobs`
+#[derive(Clone, Debug)]
+pub struct Jobs<C>
+where
+    C: Clone + Connect,
+{
+    travis: Client<C>,
+    build_id: usize,
+}
+
+impl<C> Jobs<C>
+where
+    C: Clone + Connect,
+{
+    /// lists the build's jobs
+    pub fn list(&self) -> Result<Vec<Job>> {
+        let build_id = self.build_id;
+        self.travis.wait(move |travis| travis.jobs(build_id).list())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Credential;
+    use error::{Error, ErrorKind};
+    use hyper::Client as HyperClient;
+    use hyper::client::HttpConnector;
+    use testing::{Canned, serve};
+
+    fn client(host: String) -> Result<Client<HttpConnector>> {
+        Client::spawn(move |handle| {
+            let http = HyperClient::configure().keep_alive(false).build(
+                handle,
+            );
+            ::Client::custom_async(
+                host,
+                http,
+                Some(Credential::Token("test-token".into())),
+                handle,
+            )
+        })
+    }
+
+    fn builds(ids: &[usize], next: Option<&str>) -> Canned {
+        let builds = ids.iter()
+            .map(|id| {
+                format!(
+                    r#"{{"id": {id}, "number": "{id}", "state": "passed",
+                    "duration": null, "event_type": "push",
+                    "previous_state": null, "pull_request_title": null,
+                    "pull_request_number": null, "started_at": null,
+                    "finished_at": null, "branch": {{"name": "master"}},
+                    "jobs": [], "created_by": {{"id": 1, "login": "foo"}}}}"#,
+                    id = id
+                )
+            })
+            .collect::<Vec<_>>();
+        let next = next.map(|href| format!(r#"{{"@href": "{}"}}"#, href))
+            .unwrap_or_else(|| "null".into());
+        Canned::new(
+            200,
+            format!(
+                r#"{{"builds": [{}], "@pagination": {{"limit": 2,
+                "offset": 0, "count": 3,
+                "first": {{"@href": "/repo/foo%2Fbar/builds"}},
+                "next": {}}}}}"#,
+                builds.join(","),
+                next
+            ),
+        )
+    }
+
+    #[test]
+    fn operations_block_until_complete() {
+        let (host, requests) = serve(vec![
+            Canned::new(200, r#"{"jobs": []}"#),
+            Canned::new(204, ""),
+        ]);
+        let travis = client(host).unwrap();
+        assert!(travis.jobs(1).list().unwrap().is_empty());
+        travis.env("foo/bar").delete("1").unwrap();
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["GET /build/1/jobs", "DELETE /repo/foo%2Fbar/env_var/1"]
+        );
+    }
+
+    #[test]
+    fn pages_are_iterated() {
+        let (host, requests) = serve(vec![
+            builds(&[3, 2], Some("/repo/foo%2Fbar/builds?offset=2&limit=2")),
+            builds(&[1], None),
+        ]);
+        let travis = client(host).unwrap();
+        let ids = travis
+            .builds("foo/bar")
+            .iter(&Default::default())
+            .map(|build| build.unwrap().id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 2, 1]);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn failed_construction_is_reported() {
+        let result: Result<Client<HttpConnector>> = Client::spawn(|_| {
+            Box::new(::futures::future::err(ErrorKind::Stopped.into()))
+        });
+        match result {
+            Err(Error(ErrorKind::Stopped, _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Configuration of travis clients
+
+use {Client, Credential, Future, OSS_HOST};
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use Result;
+use connect::{Connector, Proxy};
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use connect::{self, DefaultConnector};
+use hyper::Client as HyperClient;
+use hyper::client::Connect;
+use hyper::header::{Header, Headers};
+use rate_limit::{Slots, Throttle};
+use retry::RetryPolicy;
+use std::time::Duration;
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use tokio_core::reactor::Core;
+use tokio_core::reactor::Handle;
+
+/// Builds `Client`s with non-default transport settings
+///
+/// ```no_run
+/// extern crate tokio_core;
+/// extern crate travis;
+///
+/// use std::time::Duration;
+/// use tokio_core::reactor::Core;
+/// use travis::{ClientBuilder, PRO_HOST};
+///
+/// fn main() {
+///   let mut core = Core::new().unwrap();
+///   let travis = ClientBuilder::new()
+///     .host(PRO_HOST)
+///     .timeout(Duration::from_secs(30))
+///     .connect_timeout(Duration::from_secs(5))
+///     .user_agent_suffix("my-app/1.0")
+///     .build(&mut core);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    host: String,
+    credential: Option<Credential>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    dns_threads: usize,
+    keep_alive: bool,
+    keep_alive_timeout: Option<Duration>,
+    user_agent_suffix: Option<String>,
+    headers: Headers,
+    proxy: Option<Proxy>,
+    #[cfg(any(feature = "tls", feature = "rustls-tls"))]
+    roots: Vec<Vec<u8>>,
+    retry: RetryPolicy,
+    throttle: Option<Throttle>,
+    pool_size: Option<usize>,
+    log_secrets: bool,
+    discovery: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            host: OSS_HOST.into(),
+            credential: None,
+            timeout: None,
+            connect_timeout: None,
+            dns_threads: 4,
+            keep_alive: true,
+            keep_alive_timeout: Some(Duration::from_secs(90)),
+            user_agent_suffix: None,
+            headers: Headers::new(),
+            proxy: None,
+            #[cfg(any(feature = "tls", feature = "rustls-tls"))]
+            roots: Vec::new(),
+            retry: RetryPolicy::none(),
+            throttle: None,
+            pool_size: None,
+            log_secrets: false,
+            discovery: false,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Creates a builder for open source github repository builds with
+    /// the same settings as `Client::oss`
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The travis API host, `OSS_HOST` by default
+    pub fn host<H>(mut self, host: H) -> Self
+    where
+        H: Into<String>,
+    {
+        self.host = host.into();
+        self
+    }
+
+    /// Credential to authenticate with
+    pub fn credential(mut self, credential: Credential) -> Self {
+        self.credential = Some(credential);
+        self
+    }
+
+    /// Fails each request attempt which doesn't complete within `timeout`
+    ///
+    /// Timed out attempts are retried as permitted by the retry policy
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fails connections which aren't established within `timeout`
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Number of threads resolving hosts, 4 by default
+    pub fn dns_threads(mut self, threads: usize) -> Self {
+        self.dns_threads = threads;
+        self
+    }
+
+    /// Reuse pooled connections between requests, enabled by default
+    pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// How long pooled connections may sit idle before they're closed,
+    /// 90 seconds by default. `None` keeps them open indefinitely
+    pub fn keep_alive_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// Appended to the `Travis/<version>` user agent, identifying your
+    /// application to travis
+    pub fn user_agent_suffix<S>(mut self, suffix: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.user_agent_suffix = Some(suffix.into());
+        self
+    }
+
+    /// A header sent with every request
+    ///
+    /// Headers the client manages itself, like `Authorization`, take
+    /// precedence
+    pub fn header<H>(mut self, header: H) -> Self
+    where
+        H: Header,
+    {
+        self.headers.set(header);
+        self
+    }
+
+    /// Tunnels connections through `proxy`, or connects directly when
+    /// `None`
+    ///
+    /// By default connections are direct. Pass `Proxy::from_env()?` to use
+    /// the proxies configured by the environment
+    pub fn proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Trusts the DER encoded root `certificate` when verifying https
+    /// hosts, like travis enterprise instances signed by an internal CA
+    #[cfg(any(feature = "tls", feature = "rustls-tls"))]
+    pub fn root_certificate(mut self, certificate: Vec<u8>) -> Self {
+        self.roots.push(certificate);
+        self
+    }
+
+    /// Retries requests as described by `policy`. See
+    /// `Client::with_retry_policy`
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Delays requests to stay within `throttle`'s rate. See
+    /// `Client::with_throttle`
+    pub fn throttle(mut self, throttle: Throttle) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Sends at most `size` requests at once. See `Client::with_pool_size`
+    pub fn pool_size(mut self, size: usize) -> Self {
+        self.pool_size = Some(size);
+        self
+    }
+
+    /// Logs credentials, tokens and env var values. See
+    /// `Client::dangerously_log_secrets`
+    pub fn dangerously_log_secrets(mut self) -> Self {
+        self.log_secrets = true;
+        self
+    }
+
+    /// Addresses typed resources by the actions travis publishes. See
+    /// `Client::with_discovery`
+    pub fn discovery(mut self) -> Self {
+        self.discovery = true;
+        self
+    }
+
+    /// A plain http connector with this builder's connect settings
+    pub fn connector(&self, handle: &Handle) -> Connector {
+        Connector::new(self.dns_threads, handle)
+            .timeout(self.connect_timeout)
+            .proxy(self.proxy.clone())
+    }
+
+    /// A hyper client using `connector` with this builder's connection
+    /// pool settings
+    pub fn http_with<C>(&self, connector: C, handle: &Handle) -> HyperClient<C>
+    where
+        C: Clone + Connect,
+    {
+        HyperClient::configure()
+            .connector(connector)
+            .keep_alive(self.keep_alive)
+            .keep_alive_timeout(self.keep_alive_timeout)
+            .build(handle)
+    }
+
+    /// A plain http hyper client with this builder's settings, for
+    /// travis hosts which aren't served over https
+    pub fn http(&self, handle: &Handle) -> HyperClient<Connector> {
+        self.http_with(self.connector(handle), handle)
+    }
+
+    /// An https hyper client with this builder's settings
+    #[cfg(any(feature = "tls", feature = "rustls-tls"))]
+    pub fn https(
+        &self,
+        handle: &Handle,
+    ) -> Result<HyperClient<DefaultConnector>> {
+        let connector = connect::https(self.connector(handle), &self.roots)?;
+        Ok(self.http_with(connector, handle))
+    }
+
+    /// Creates a client sending requests with `http`, resolving once
+    /// `Credential::Github` credentials are exchanged for a travis token
+    ///
+    /// `handle` drives timeouts, retries and throttling
+    pub fn build_with<C>(
+        self,
+        http: HyperClient<C>,
+        handle: &Handle,
+    ) -> Future<Client<C>>
+    where
+        C: Clone + Connect,
+    {
+        let mut user_agent = format!("Travis/{}", env!("CARGO_PKG_VERSION"));
+        if let Some(suffix) = self.user_agent_suffix {
+            user_agent.push(' ');
+            user_agent.push_str(&suffix);
+        }
+        let mut client = Client::new(self.host, http, handle)
+            .with_retry_policy(self.retry);
+        client.timeout = self.timeout;
+        client.user_agent = user_agent;
+        client.headers = self.headers;
+        client.throttle = self.throttle;
+        client.slots = self.pool_size.map(Slots::new);
+        client.log_secrets = self.log_secrets;
+        client.discovery = self.discovery;
+        client.authenticate(self.credential)
+    }
+
+    /// Creates an https client without blocking on credential exchange
+    #[cfg(any(feature = "tls", feature = "rustls-tls"))]
+    pub fn build_async(
+        self,
+        handle: &Handle,
+    ) -> Future<Client<DefaultConnector>> {
+        match self.https(handle) {
+            Ok(http) => self.build_with(http, handle),
+            Err(err) => Box::new(::futures::future::err(err)),
+        }
+    }
+
+    /// Creates an https client, blocking on `core` to exchange
+    /// `Credential::Github` credentials for a travis token
+    #[cfg(any(feature = "tls", feature = "rustls-tls"))]
+    pub fn build(self, core: &mut Core) -> Result<Client<DefaultConnector>> {
+        let client = self.build_async(&core.handle());
+        core.run(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ErrorKind;
+    use hyper::Error as HttpError;
+    use testing::{Canned, serve, serve_heads};
+    use tokio_core::reactor::Core;
+
+    header! {
+        (XRequestedBy, "X-Requested-By") => [String]
+    }
+
+    #[test]
+    fn builder_configures_requests() {
+        let (host, heads) = serve_heads(vec![
+            Canned::new(200, r#"{"env_vars": []}"#),
+        ]);
+        let mut core = Core::new().unwrap();
+        let builder = ClientBuilder::new()
+            .host(host)
+            .credential(Credential::Token("test-token".into()))
+            .keep_alive(false)
+            .user_agent_suffix("ci-bot/1.0")
+            .header(XRequestedBy("ci-bot".into()));
+        let handle = core.handle();
+        let http = builder.http(&handle);
+        let travis = core.run(builder.build_with(http, &handle)).unwrap();
+        core.run(travis.env("foo/bar").vars()).unwrap();
+        let head = heads.lock().unwrap()[0].to_lowercase();
+        assert!(head.contains(&format!(
+            "user-agent: travis/{} ci-bot/1.0\r\n",
+            env!("CARGO_PKG_VERSION")
+        )));
+        assert!(head.contains("x-requested-by: ci-bot\r\n"));
+        assert!(head.contains("authorization: token test-token\r\n"));
+    }
+
+    #[test]
+    fn stalled_requests_time_out() {
+        let (host, _) = serve(vec![
+            Canned::new(200, r#"{"env_vars": []}"#)
+                .delay(Duration::from_secs(2)),
+        ]);
+        let mut core = Core::new().unwrap();
+        let builder = ClientBuilder::new()
+            .host(host)
+            .keep_alive(false)
+            .timeout(Duration::from_millis(100));
+        let handle = core.handle();
+        let http = builder.http(&handle);
+        let travis = core.run(builder.build_with(http, &handle)).unwrap();
+        match core.run(travis.env("foo/bar").vars()) {
+            Err(ref err) => {
+                match *err.kind() {
+                    ErrorKind::Http(HttpError::Timeout) => (),
+                    ref otherwise => panic!("unexpected {:?}", otherwise),
+                }
+            }
+            Ok(_) => panic!("expected a timeout"),
+        }
+    }
+
+    #[test]
+    fn stalled_exchanges_time_out() {
+        let (host, heads) = serve_heads(vec![
+            Canned::new(200, r#"{"access_token": "travis-token"}"#)
+                .delay(Duration::from_secs(2)),
+        ]);
+        let mut core = Core::new().unwrap();
+        let builder = ClientBuilder::new()
+            .host(host)
+            .credential(Credential::Github("github-token".into()))
+            .keep_alive(false)
+            .header(XRequestedBy("ci-bot".into()))
+            .timeout(Duration::from_millis(100));
+        let handle = core.handle();
+        let http = builder.http(&handle);
+        match core.run(builder.build_with(http, &handle)) {
+            Err(ref err) => {
+                match *err.kind() {
+                    ErrorKind::Http(HttpError::Timeout) => (),
+                    ref otherwise => panic!("unexpected {:?}", otherwise),
+                }
+            }
+            Ok(_) => panic!("expected a timeout"),
+        }
+        let head = heads.lock().unwrap()[0].to_lowercase();
+        assert!(head.starts_with("post /auth/github "));
+        assert!(head.contains("x-requested-by: ci-bot\r\n"));
+    }
+}
@@ -1,11 +1,14 @@
 //! interfaces for interacting with travis builds
 
-use {Branch, Client, Error, Stream, Future, Owner, Pagination, State};
-use futures::{
-    future, stream, Future as StdFuture, IntoFuture, Stream as StdStream
-    };
+use {Branch, Client, Commit, Embedded, Future, Minimal, Owner, State, Stream,
+     Timestamp, escape};
+use futures::{future, Future as StdFuture};
+use hyper::Uri;
 use hyper::client::Connect;
-use jobs::Job;
+use jobs::{Job, MinimalJob};
+use pagination::{PageOptions, Paginated, Pagination};
+use repos::{MinimalRepository, Repository};
+use std::fmt;
 use url::form_urlencoded::Serializer;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -15,9 +18,63 @@ struct Wrapper {
     pagination: Pagination,
 }
 
+impl Paginated for Wrapper {
+    type Item = Build;
+
+    fn pagination(&self) -> &Pagination {
+        &self.pagination
+    }
+
+    fn into_items(self) -> Vec<Build> {
+        self.builds
+    }
+}
+
+/// A build's minimal representation, as embedded in other resources
+#[derive(Debug, Deserialize, Clone)]
+pub struct MinimalBuild {
+    pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
+    pub number: String,
+    pub state: State,
+    pub duration: Option<usize>,
+    pub event_type: String,
+    pub previous_state: Option<State>,
+    pub started_at: Option<Timestamp>,
+    pub finished_at: Option<Timestamp>,
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
+}
+
+impl Minimal for MinimalBuild {
+    type Standard = Build;
+
+    const STANDARD_FIELDS: &'static [&'static str] = &[
+        "pull_request_title",
+        "pull_request_number",
+        "created_at",
+        "repository",
+        "branch",
+        "commit",
+        "jobs",
+        "stages",
+        "created_by",
+    ];
+
+    fn path(&self) -> String {
+        self.href.clone().unwrap_or_else(|| format!("/build/{}", self.id))
+    }
+}
+
+/// A build's standard representation
 #[derive(Debug, Deserialize, Clone)]
 pub struct Build {
     pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
     pub number: String,
     pub state: State,
     pub duration: Option<usize>,
@@ -25,21 +82,118 @@ pub struct Build {
     pub previous_state: Option<State>,
     pub pull_request_title: Option<String>,
     pub pull_request_number: Option<usize>,
-    pub started_at: Option<String>,
-    pub finished_at: Option<String>,
-    // repository
+    /// when the build was created, if reported
+    #[serde(default)]
+    pub created_at: Option<Timestamp>,
+    pub started_at: Option<Timestamp>,
+    pub finished_at: Option<Timestamp>,
+    /// standard when listed with `Include::Repository`
+    #[serde(default)]
+    pub repository: Option<Embedded<MinimalRepository, Repository>>,
     pub branch: Branch,
-    // commit
-    pub jobs: Vec<Job>,
-    // stages
+    /// populated when listed with `Include::Commit`
+    #[serde(default)]
+    pub commit: Option<Commit>,
+    /// standard when listed with `Include::Jobs`
+    pub jobs: Vec<Embedded<MinimalJob, Job>>,
+    /// populated when listed with `Include::Stages`
+    #[serde(default)]
+    pub stages: Option<Vec<Stage>>,
     pub created_by: Owner,
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
+}
+
+#[cfg(feature = "chrono")]
+impl Build {
+    /// time spent waiting between creation and starting
+    pub fn queue_time(&self) -> Option<::std::time::Duration> {
+        ::between(&self.created_at, &self.started_at)
+    }
+
+    /// time elapsed between starting and finishing
+    pub fn elapsed(&self) -> Option<::std::time::Duration> {
+        ::between(&self.started_at, &self.finished_at)
+    }
+}
+
+/// A build stage
+#[derive(Debug, Deserialize, Clone)]
+pub struct Stage {
+    pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
+    pub number: Option<usize>,
+    pub name: Option<String>,
+    pub state: Option<State>,
+    pub started_at: Option<Timestamp>,
+    pub finished_at: Option<Timestamp>,
+}
+
+/// Embedded resources which may be included with listed builds
+#[derive(Debug, Clone, PartialEq)]
+pub enum Include {
+    /// `build.jobs`
+    Jobs,
+    /// `build.commit`
+    Commit,
+    /// `build.stages`
+    Stages,
+    /// `build.repository`
+    Repository,
+    /// `build.branch`
+    Branch,
+    /// `build.created_by`
+    CreatedBy,
+    /// any other include, passed through as is
+    Raw(String),
+}
+
+impl<'a> From<&'a str> for Include {
+    fn from(include: &'a str) -> Self {
+        match include {
+            "build.jobs" => Include::Jobs,
+            "build.commit" => Include::Commit,
+            "build.stages" => Include::Stages,
+            "build.repository" => Include::Repository,
+            "build.branch" => Include::Branch,
+            "build.created_by" => Include::CreatedBy,
+            other => Include::Raw(other.to_owned()),
+        }
+    }
+}
+
+impl From<String> for Include {
+    fn from(include: String) -> Self {
+        Include::from(include.as_ref())
+    }
+}
+
+impl fmt::Display for Include {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Include::Jobs => "build.jobs",
+                Include::Commit => "build.commit",
+                Include::Stages => "build.stages",
+                Include::Repository => "build.repository",
+                Include::Branch => "build.branch",
+                Include::CreatedBy => "build.created_by",
+                Include::Raw(ref include) => include,
+            }
+        )
+    }
 }
 
 /// list options
-#[derive(Builder, Debug)]
+#[derive(Builder, Debug, Clone)]
 #[builder(setter(into), default)]
 pub struct ListOptions {
-    include: Vec<String>,
+    include: Vec<Include>,
     limit: i32,
     /// id, started_at, finished_at,
     /// append :desc to any attribute to reverse order.
@@ -57,7 +211,14 @@ impl ListOptions {
 
     fn into_query_string(&self) -> String {
         let mut params = vec![
-            ("include", self.include.join(",")),
+            (
+                "include",
+                self.include
+                    .iter()
+                    .map(Include::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
             ("limit", self.limit.to_string()),
             ("sort_by", self.sort_by.clone()),
         ];
@@ -107,85 +268,57 @@ where
     pub fn list(&self, options: &ListOptions) -> Future<Vec<Build>> {
         Box::new(
             self.travis
-                .get(
-                    format!(
-                        "{host}/repo/{slug}/builds?{query}",
-                        host = self.travis.host,
-                        slug = self.slug,
-                        query = options.into_query_string()
-                    ).parse()
-                        .map_err(Error::from)
-                        .into_future(),
-                )
+                .get(self.locate(options))
                 .and_then(|wrapper: Wrapper| future::ok(wrapper.builds)),
         )
     }
 
-    pub fn iter(
+    pub fn iter(&self, options: &ListOptions) -> Stream<Build> {
+        self.iter_with(options, &PageOptions::default())
+    }
+
+    /// streams builds across pages, requesting them as described by `paging`
+    pub fn iter_with(
         &self,
         options: &ListOptions,
+        paging: &PageOptions,
     ) -> Stream<Build> {
-        let first = self.travis
-            .get::<Wrapper>(
-                format!(
-                    "{host}/repo/{slug}/builds?{query}",
-                    host = self.travis.host,
-                    slug = self.slug,
-                    query = options.into_query_string()
-                ).parse()
-                    .map_err(Error::from)
-                    .into_future(),
-            )
-            .map(|mut wrapper: Wrapper| {
-                let mut builds = wrapper.builds;
-                builds.reverse();
-                wrapper.builds = builds;
-                wrapper
-            });
-        // needed to move "self" into the closure below
-        let clone = self.clone();
-        Box::new(
-            first
-                .map(move |wrapper| {
-                    stream::unfold::<_, _, Future<(Build, Wrapper)>, _>(
-                        wrapper,
-                        move |mut state| match state.builds.pop() {
-                            Some(build) => Some(
-                                Box::new(future::ok((build, state))) as
-                                    Future<(Build, Wrapper)>,
-                            ),
-                            _ => {
-                                state.pagination.next.clone().map(|path| {
-                                    Box::new(
-                                        clone
-                                            .travis
-                                            .get::<Wrapper>(
-                                                format!(
-                                                    "{host}{path}",
-                                                    host = clone.travis.host,
-                                                    path = path.href
-                                                ).parse()
-                                                    .map_err(Error::from)
-                                                    .into_future(),
-                                            )
-                                            .map(|mut next| {
-                                                let mut builds = next.builds;
-                                                builds.reverse();
-                                                next.builds = builds;
-                                                (
-                                                    next.builds.pop().unwrap(),
-                                                    next,
-                                                )
-                                            }),
-                                    ) as
-                                        Future<(Build, Wrapper)>
-                                })
-                            }
-                        },
-                    )
-                })
-                .into_stream()
-                .flatten(),
+        self.travis.paginate::<Wrapper>(self.locate(options), paging)
+    }
+
+    /// the uri of this repo's builds
+    fn locate(&self, options: &ListOptions) -> Future<Uri> {
+        self.travis.locate(
+            "builds",
+            "find",
+            &[("repository.slug", &self.slug)],
+            format!("/repo/{}/builds", escape(&self.slug)),
+            options.into_query_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_are_typed() {
+        let options = ListOptions::builder()
+            .include(vec![Include::Jobs, "build.commit".into()])
+            .build()
+            .unwrap();
+        assert_eq!(options.include, vec![Include::Jobs, Include::Commit]);
+        assert!(options.into_query_string().starts_with(
+            "include=build.jobs%2Cbuild.commit&",
+        ));
+    }
+
+    #[test]
+    fn raw_includes_pass_through() {
+        assert_eq!(
+            Include::from("job.config").to_string(),
+            "job.config".to_owned()
         )
     }
 }
@@ -0,0 +1,466 @@
+//! Caching responses and revalidating them with conditional requests
+//!
+//! Clients configured with `Client::with_cache` store the `ETag` and
+//! `Last-Modified` validators travis sends with successful `GET`
+//! responses, keyed by url and a hash of the request's credentials. Later
+//! requests for the same url with the same credentials are sent with
+//! `If-None-Match` and `If-Modified-Since` headers, and when travis
+//! responds `304 Not Modified` the cached response is used in its place.
+//!
+//! Cached responses may hold private data, like the env vars of private
+//! repositories. `DiskStore` writes them unencrypted, readable only by
+//! the current user on unix.
+//!
+//! ```no_run
+//! extern crate tokio_core;
+//! extern crate travis;
+//!
+//! use tokio_core::reactor::Core;
+//! use travis::Client;
+//! use travis::cache::MemoryStore;
+//!
+//! fn main() {
+//!   let mut core = Core::new().unwrap();
+//!   let travis = Client::oss(None, &mut core)
+//!     .unwrap()
+//!     .with_cache(MemoryStore::new(100));
+//!   let builds = travis.builds("softprops/travis");
+//!   // unchanged builds are only transferred once
+//!   for _ in 0..2 {
+//!     core.run(builds.list(&Default::default())).unwrap();
+//!   }
+//! }
+//! ```
+
+use {Error, Future};
+use futures::Future as StdFuture;
+use hyper::{Method, StatusCode};
+use hyper::header::{Authorization, ETag, LastModified};
+use serde_json;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use transport::{Layer, Request, Response, Transport};
+
+/// A cached response along with its validators
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Entry {
+    /// the url of the request the response was received for, followed by
+    /// a hash of its credentials when it had any
+    pub key: String,
+    /// the response's `ETag`
+    pub etag: Option<String>,
+    /// the response's `Last-Modified` date
+    pub last_modified: Option<String>,
+    /// the response's body
+    pub body: String,
+}
+
+/// Storage for cached responses
+///
+/// Stores are best effort: entries may be evicted at any time, and
+/// failing to store one only means its url is requested unconditionally
+pub trait Store {
+    /// the entry cached under `key`, if any
+    fn get(&self, key: &str) -> Option<Entry>;
+
+    /// caches `entry`, replacing any entry with the same key
+    fn put(&self, entry: Entry);
+}
+
+/// A store holding up to a fixed number of entries in memory, evicting
+/// the least recently used
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    /// entries, least recently used first
+    entries: RefCell<VecDeque<Entry>>,
+}
+
+impl MemoryStore {
+    /// Creates a store holding up to `capacity` entries. A capacity of 0
+    /// stores nothing
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            capacity,
+            entries: Default::default(),
+        }
+    }
+
+    /// The number of entries currently stored
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> Option<Entry> {
+        let mut entries = self.entries.borrow_mut();
+        let at = entries.iter().position(|entry| entry.key == key)?;
+        let entry = entries.remove(at)?;
+        entries.push_back(entry.clone());
+        Some(entry)
+    }
+
+    fn put(&self, entry: Entry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.borrow_mut();
+        entries.retain(|cached| cached.key != entry.key);
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+/// A store keeping one JSON file per entry in a directory, so cached
+/// responses outlive the process
+///
+/// Responses are stored as received, unencrypted. On unix their files are
+/// only readable by the current user.
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    /// Stores entries in `dir`, creating it if needed
+    pub fn new<P>(dir: P) -> io::Result<Self>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(DiskStore { dir })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", hash(key)))
+    }
+}
+
+/// creates a new file only its owner may read, on unix
+fn create(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+impl Store for DiskStore {
+    fn get(&self, key: &str) -> Option<Entry> {
+        let file = File::open(self.path(key)).ok()?;
+        serde_json::from_reader::<_, Entry>(file).ok().and_then(|entry| {
+            // keys sharing a file replace each other's entries
+            if entry.key == key { Some(entry) } else { None }
+        })
+    }
+
+    fn put(&self, entry: Entry) {
+        let path = self.path(&entry.key);
+        // written aside and renamed into place, so readers never see a
+        // partial entry and replaced files get fresh permissions
+        let temp = path.with_extension(format!("{}.tmp", process::id()));
+        let _ = fs::remove_file(&temp);
+        let written = create(&temp)
+            .map_err(Error::from)
+            .and_then(|file| {
+                serde_json::to_writer(file, &entry).map_err(Error::from)
+            })
+            .and_then(|_| fs::rename(&temp, &path).map_err(Error::from));
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp);
+            warn!("failed to cache {} in {:?}: {}", entry.key, path, err)
+        }
+    }
+}
+
+/// the 64 bit FNV-1a hash of `value`, stable across rust releases so
+/// file names outlive toolchain upgrades
+fn hash(value: &str) -> u64 {
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// the key `request`'s response is cached under, so responses to
+/// different credentials are kept apart
+fn key(request: &Request) -> String {
+    match request.headers.get::<Authorization<String>>() {
+        Some(credentials) => {
+            format!("{} {:016x}", request.url, hash(credentials.as_str()))
+        }
+        None => request.url.clone(),
+    }
+}
+
+/// A `Layer` caching `GET` responses in a store. See `Client::with_cache`
+#[derive(Clone)]
+pub struct Cache {
+    store: Rc<Store>,
+}
+
+impl Cache {
+    /// Creates a layer caching responses in `store`
+    pub fn new<S>(store: S) -> Self
+    where
+        S: Store + 'static,
+    {
+        Cache { store: Rc::new(store) }
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Cache")
+    }
+}
+
+impl Layer for Cache {
+    fn layer(&self, inner: Rc<Transport>) -> Rc<Transport> {
+        Rc::new(Caching {
+            inner,
+            store: self.store.clone(),
+        })
+    }
+}
+
+/// a transport revalidating cached responses
+struct Caching {
+    inner: Rc<Transport>,
+    store: Rc<Store>,
+}
+
+impl Transport for Caching {
+    fn send(&self, mut request: Request) -> Future<Response> {
+        if request.method != Method::Get {
+            return self.inner.send(request);
+        }
+        let key = key(&request);
+        let cached = self.store.get(&key);
+        if let Some(ref entry) = cached {
+            if let Some(ref etag) = entry.etag {
+                request.headers.set_raw("If-None-Match", etag.clone());
+            }
+            if let Some(ref date) = entry.last_modified {
+                request.headers.set_raw("If-Modified-Since", date.clone());
+            }
+        }
+        let url = request.url.clone();
+        let store = self.store.clone();
+        Box::new(self.inner.send(request).map(move |response| {
+            match (response.status, cached) {
+                (StatusCode::NotModified, Some(entry)) => {
+                    debug!("using cached response for {}", url);
+                    Response {
+                        status: StatusCode::Ok,
+                        headers: response.headers,
+                        body: entry.body.into_bytes(),
+                    }
+                }
+                (status, _) if status.is_success() => {
+                    let etag = response.headers.get::<ETag>().map(
+                        ToString::to_string,
+                    );
+                    let last_modified = response
+                        .headers
+                        .get::<LastModified>()
+                        .map(ToString::to_string);
+                    let body = String::from_utf8(response.body.clone());
+                    match body {
+                        Ok(body) if etag.is_some() ||
+                                        last_modified.is_some() => {
+                            store.put(Entry {
+                                key,
+                                etag,
+                                last_modified,
+                                body,
+                            })
+                        }
+                        _ => (),
+                    }
+                    response
+                }
+                _ => response,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::EntityTag;
+    use hyper::header::Headers;
+    use std::env;
+    use std::process;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use testing::transported;
+    use tokio_core::reactor::Core;
+    use transport::Memory;
+
+    const VARS: &str = "/repo/foo%2Fbar/env_vars";
+
+    fn tagged(tag: &str, body: &str) -> Response {
+        let mut response = Response::new(StatusCode::Ok, body);
+        response.headers.set(ETag(EntityTag::strong(tag.into())));
+        response
+    }
+
+    fn entry(key: &str) -> Entry {
+        Entry {
+            key: key.into(),
+            etag: Some("\"1\"".into()),
+            last_modified: None,
+            body: "{}".into(),
+        }
+    }
+
+    #[test]
+    fn unmodified_responses_are_served_from_the_cache() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond_with(
+            Method::Get,
+            VARS,
+            tagged("v1", r#"{"env_vars": [{"id": "1", "name": "A",
+              "@permissions": {"read": true, "write": true}}]}"#),
+        );
+        memory.respond(Method::Get, VARS, 304, "");
+        let travis = transported(memory.clone(), &mut core)
+            .with_cache(MemoryStore::new(10));
+        for _ in 0..2 {
+            let vars = core.run(travis.env("foo/bar").vars()).unwrap();
+            assert_eq!(vars[0].name, Some("A".into()));
+        }
+        let requests = memory.requests();
+        assert_eq!(requests[0].headers.get_raw("If-None-Match"), None);
+        assert_eq!(
+            requests[1].headers.get_raw("If-None-Match").and_then(
+                |raw| raw.one(),
+            ),
+            Some(&b"\"v1\""[..])
+        );
+    }
+
+    #[test]
+    fn modified_responses_replace_cached_ones() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond_with(Method::Get, VARS, tagged("v1", "{}"));
+        memory.respond_with(Method::Get, VARS, tagged("v2", "[]"));
+        let store = Rc::new(MemoryStore::new(10));
+        let caching = Cache { store: store.clone() }.layer(Rc::new(memory));
+        let request = || {
+            Request {
+                method: Method::Get,
+                url: format!("https://api.travis-ci.org{}", VARS),
+                headers: Headers::new(),
+                body: None,
+            }
+        };
+        core.run(caching.send(request())).unwrap();
+        let response = core.run(caching.send(request())).unwrap();
+        assert_eq!(response.body, b"[]");
+        let cached = store.get(&key(&request())).unwrap();
+        assert_eq!(cached.etag, Some("\"v2\"".into()));
+        assert_eq!(cached.body, "[]");
+    }
+
+    #[test]
+    fn memory_stores_evict_least_recently_used_entries() {
+        let store = MemoryStore::new(2);
+        store.put(entry("/a"));
+        store.put(entry("/b"));
+        store.get("/a");
+        store.put(entry("/c"));
+        assert_eq!(store.len(), 2);
+        assert!(store.get("/a").is_some());
+        assert!(store.get("/b").is_none());
+        assert!(store.get("/c").is_some());
+    }
+
+    #[test]
+    fn credentials_are_cached_apart() {
+        let memory = Memory::new();
+        memory.respond_with(Method::Get, VARS, tagged("v1", "{}"));
+        let caching = Cache::new(MemoryStore::new(10))
+            .layer(Rc::new(memory.clone()));
+        let request = |token: &str| {
+            let mut headers = Headers::new();
+            headers.set(Authorization(format!("token {}", token)));
+            Request {
+                method: Method::Get,
+                url: format!("https://api.travis-ci.org{}", VARS),
+                headers,
+                body: None,
+            }
+        };
+        let mut core = Core::new().unwrap();
+        core.run(caching.send(request("a"))).unwrap();
+        core.run(caching.send(request("b"))).unwrap();
+        core.run(caching.send(request("a"))).unwrap();
+        let revalidated = memory
+            .requests()
+            .iter()
+            .map(|request| request.headers.get_raw("If-None-Match").is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(revalidated, vec![false, false, true]);
+        assert_ne!(key(&request("a")), key(&request("b")));
+    }
+
+    /// a directory no other test uses
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "travis-{}-{}-{}",
+            name,
+            process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos()
+        ))
+    }
+
+    #[test]
+    fn disk_stores_persist_entries() {
+        let dir = scratch("disk-store");
+        let store = DiskStore::new(&dir).unwrap();
+        store.put(entry("/a"));
+        assert_eq!(
+            DiskStore::new(&dir).unwrap().get("/a"),
+            Some(entry("/a"))
+        );
+        assert_eq!(store.get("/b"), None);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(store.path("/a")).unwrap().permissions();
+            assert_eq!(mode.mode() & 0o777, 0o600);
+        }
+        store.put(entry("/a"));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        assert_eq!(hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,202 @@
+//! Travis API credentials and their discovery
+
+use error::Result;
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Representation of types of API credentials used to authenticate the client
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credential {
+    /// A Travis API token
+    ///
+    /// Typically obtained from `travis token` ruby cli
+    Token(String),
+    /// A Github API token
+    ///
+    /// This will be immediately exchanged for a travis token
+    /// after constructing a `travis::Client` instance.
+    /// Care should be taken to associate appropriate
+    /// [Github scopes](https://docs.travis-ci.com/user/github-oauth-scopes/)
+    /// with these tokens to perform target operations for on oss vs private
+    /// repositories
+    Github(String),
+}
+
+/// Where a discovered credential was found
+#[derive(Clone, Debug, PartialEq)]
+pub enum CredentialSource {
+    /// the named environment variable
+    Env(String),
+    /// the travis ruby cli's config file at this path
+    Config(PathBuf),
+}
+
+/// The subset of the ruby cli's `config.yml` needed to find tokens
+#[derive(Debug, Deserialize)]
+struct Config {
+    #[serde(default)]
+    endpoints: BTreeMap<String, Endpoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Endpoint {
+    access_token: Option<String>,
+}
+
+impl Credential {
+    /// Discovers a credential for a travis API endpoint, like
+    /// `travis::OSS_HOST`, `travis::PRO_HOST` or an enterprise host
+    ///
+    /// Sources are checked in this order
+    ///
+    /// * the `TRAVIS_TOKEN` environment variable, as a `Credential::Token`
+    /// * the `GITHUB_TOKEN` environment variable, as a `Credential::Github`
+    /// * the token the travis ruby cli stored for `endpoint` in
+    ///   `~/.travis/config.yml`, or in the directory named by
+    ///   `TRAVIS_CONFIG_PATH`
+    ///
+    /// Resolves to `None` when no source provides a credential.
+    pub fn discover(
+        endpoint: &str,
+    ) -> Result<Option<(Credential, CredentialSource)>> {
+        let config = env::var_os("TRAVIS_CONFIG_PATH")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".travis")))
+            .map(|dir| dir.join("config.yml"));
+        discover(endpoint, |name| env::var(name).ok(), config)
+    }
+}
+
+/// the user's home directory, from `HOME` or on windows `USERPROFILE`
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+fn discover<E>(
+    endpoint: &str,
+    var: E,
+    config: Option<PathBuf>,
+) -> Result<Option<(Credential, CredentialSource)>>
+where
+    E: Fn(&str) -> Option<String>,
+{
+    let vars: [(&str, fn(String) -> Credential); 2] = [
+        ("TRAVIS_TOKEN", Credential::Token),
+        ("GITHUB_TOKEN", Credential::Github),
+    ];
+    for &(name, credential) in vars.iter() {
+        if let Some(token) = var(name).filter(|token| !token.is_empty()) {
+            debug!("discovered credential in {}", name);
+            return Ok(Some(
+                (credential(token), CredentialSource::Env(name.into())),
+            ));
+        }
+    }
+    match config {
+        Some(ref path) if path.exists() => {
+            Ok(configured(endpoint, path)?.map(|token| {
+                debug!("discovered credential in {}", path.display());
+                (Credential::Token(token), CredentialSource::Config(
+                    path.clone(),
+                ))
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// the access token the ruby cli stored for an endpoint
+fn configured(endpoint: &str, path: &Path) -> Result<Option<String>> {
+    let config: Config = serde_yaml::from_reader(File::open(path)?)
+        .map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+    let endpoint = endpoint.trim_end_matches('/');
+    Ok(config
+        .endpoints
+        .into_iter()
+        .find(|&(ref url, _)| url.trim_end_matches('/') == endpoint)
+        .and_then(|(_, endpoint)| endpoint.access_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::process;
+    use {OSS_HOST, PRO_HOST};
+
+    fn config(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "travis-credential-{}-{}",
+            name,
+            process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.yml");
+        File::create(&path)
+            .unwrap()
+            .write_all(
+                b"---
+endpoints:
+  https://api.travis-ci.org/:
+    access_token: oss-token
+  https://api.travis-ci.com/:
+    access_token: pro-token
+default_endpoint: https://api.travis-ci.org/
+",
+            )
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn env_vars_take_precedence() {
+        let vars = |name: &str| match name {
+            "TRAVIS_TOKEN" => Some("travis".to_owned()),
+            _ => Some("github".to_owned()),
+        };
+        assert_eq!(
+            discover(OSS_HOST, vars, Some(config("env"))).unwrap(),
+            Some((
+                Credential::Token("travis".into()),
+                CredentialSource::Env("TRAVIS_TOKEN".into()),
+            ))
+        );
+        let github = |name: &str| if name == "GITHUB_TOKEN" {
+            Some("github".to_owned())
+        } else {
+            None
+        };
+        assert_eq!(
+            discover(OSS_HOST, github, None).unwrap(),
+            Some((
+                Credential::Github("github".into()),
+                CredentialSource::Env("GITHUB_TOKEN".into()),
+            ))
+        );
+    }
+
+    #[test]
+    fn config_tokens_match_endpoints() {
+        let path = config("endpoints");
+        let none = |_: &str| None;
+        assert_eq!(
+            discover(PRO_HOST, none, Some(path.clone())).unwrap(),
+            Some((
+                Credential::Token("pro-token".into()),
+                CredentialSource::Config(path.clone()),
+            ))
+        );
+        assert_eq!(
+            discover("https://travis.example.com/api", none, Some(path))
+                .unwrap(),
+            None
+        );
+    }
+}
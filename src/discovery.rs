@@ -0,0 +1,399 @@
+//! Addressing resources by the actions travis publishes
+//!
+//! The v3 home resource (`GET /`) describes every resource's actions along
+//! with the URI templates used to perform them. Rather than the paths this
+//! crate builds for each resource, `Client::action` looks actions up in
+//! the home document of the host it's talking to, which suits deployments
+//! like Travis Enterprise whose paths may differ. Clients fetch the home
+//! document once and share it between clones.
+//!
+//! Clients created `with_discovery` address typed resources, like
+//! `Client::env`, this way too, falling back on built-in paths for actions
+//! travis doesn't publish.
+//!
+//! ```no_run
+//! extern crate serde_json;
+//! extern crate tokio_core;
+//! extern crate travis;
+//!
+//! use tokio_core::reactor::Core;
+//! use travis::Client;
+//!
+//! fn main() {
+//!   let mut core = Core::new().unwrap();
+//!   let travis = Client::oss(None, &mut core).unwrap();
+//!   let builds = core.run(travis.action::<serde_json::Value>(
+//!     "builds",
+//!     "find",
+//!     &[("repository.slug", "softprops/travis"), ("limit", "5")],
+//!   )).unwrap();
+//!   println!("{}", builds["builds"]);
+//! }
+//! ```
+
+use {Error, Result, escape};
+use error::ErrorKind;
+use hyper::Method;
+use std::collections::BTreeMap;
+use url::form_urlencoded;
+
+/// The home document travis serves at `/`
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Home {
+    /// resources by name, like `build` and `env_vars`
+    #[serde(default)]
+    pub resources: BTreeMap<String, Resource>,
+}
+
+/// A resource type and the actions which may be performed on it
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Resource {
+    /// templates for each of this resource's actions, by action name
+    #[serde(default)]
+    pub actions: BTreeMap<String, Vec<Template>>,
+}
+
+/// A way to request an action, as a method and an RFC 6570 URI template
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Template {
+    /// the http method to request the action with, like `GET`
+    pub request_method: String,
+    /// where to request the action, like `/repo/{repository.slug}`, with
+    /// query variables like `{?limit,offset}` listing its optional params
+    pub uri_template: String,
+}
+
+impl Home {
+    /// The templates for `resource`'s `action`, failing with
+    /// `ErrorKind::UnsupportedAction` when travis doesn't support it
+    pub fn templates(
+        &self,
+        resource: &str,
+        action: &str,
+    ) -> Result<&[Template]> {
+        self.resources
+            .get(resource)
+            .and_then(|published| published.actions.get(action))
+            .map(Vec::as_slice)
+            .ok_or_else(|| {
+                ErrorKind::UnsupportedAction(resource.into(), action.into())
+                    .into()
+            })
+    }
+
+    /// The method and path to request `resource`'s `action` with
+    ///
+    /// Actions may be published with several templates, addressing
+    /// resources by id or slug for example. The first whose path variables
+    /// are all in `params` is used. Params which aren't variables of any
+    /// of the action's templates are rejected.
+    pub fn expand(
+        &self,
+        resource: &str,
+        action: &str,
+        params: &BTreeMap<String, String>,
+    ) -> Result<(Method, String)> {
+        let templates = self.templates(resource, action)?;
+        let mut variables = Vec::new();
+        for template in templates {
+            variables.extend(template.variables()?);
+        }
+        if let Some(unknown) = params
+            .keys()
+            .find(|name| !variables.contains(&name.as_str()))
+        {
+            return Err(
+                format!(
+                    "unknown parameter {} for {}.{}",
+                    unknown,
+                    resource,
+                    action
+                ).into(),
+            );
+        }
+        let template = templates
+            .iter()
+            .find(|template| template.accepts(params))
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "missing parameters for {}.{}, expected one of {}",
+                    resource,
+                    action,
+                    templates
+                        .iter()
+                        .map(|template| template.uri_template.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+        let own = template.variables()?;
+        let path = template.expand(&params
+            .iter()
+            .filter(|&(name, _)| own.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect())?;
+        let method = template.request_method.parse::<Method>().map_err(
+            |_| {
+                Error::from(
+                    format!("invalid method {}", template.request_method),
+                )
+            },
+        )?;
+        Ok((method, path))
+    }
+}
+
+impl Template {
+    /// Whether `params` has a value for each of this template's path
+    /// variables
+    pub fn accepts(&self, params: &BTreeMap<String, String>) -> bool {
+        self.parts()
+            .map(|parts| {
+                parts.iter().all(|part| match *part {
+                    Part::Path(ref names) => {
+                        names.iter().all(|name| params.contains_key(*name))
+                    }
+                    _ => true,
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Expands this template with `params`
+    ///
+    /// Path variables (`{var}`) are percent encoded and required. Query
+    /// variables (`{?var}` and `{&var}`) are optional and form encoded.
+    /// Params which aren't variables of this template are rejected, so
+    /// misspelt names don't go unnoticed.
+    pub fn expand(&self, params: &BTreeMap<String, String>) -> Result<String> {
+        let parts = self.parts()?;
+        if let Some(unknown) = params.keys().find(|name| {
+            !parts.iter().any(|part| part.names().contains(&name.as_str()))
+        })
+        {
+            return Err(
+                format!(
+                    "unknown parameter {} for {}",
+                    unknown,
+                    self.uri_template
+                ).into(),
+            );
+        }
+        let mut expanded = String::new();
+        for part in parts {
+            match part {
+                Part::Literal(literal) => expanded.push_str(literal),
+                Part::Path(names) => {
+                    let values = names
+                        .into_iter()
+                        .map(|name| {
+                            let value = params.get(name).ok_or_else(|| {
+                                Error::from(format!(
+                                    "missing parameter {} for {}",
+                                    name,
+                                    self.uri_template
+                                ))
+                            })?;
+                            Ok(escape(value))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    expanded.push_str(&values.join(","));
+                }
+                Part::Query(names) => {
+                    let pairs = names
+                        .into_iter()
+                        .filter_map(|name| {
+                            params.get(name).map(|value| (name, value))
+                        })
+                        .collect::<Vec<_>>();
+                    if !pairs.is_empty() {
+                        let separator = if expanded.contains('?') {
+                            '&'
+                        } else {
+                            '?'
+                        };
+                        expanded.push(separator);
+                        expanded.push_str(&form_urlencoded::Serializer::new(
+                            String::new(),
+                        ).extend_pairs(pairs)
+                            .finish());
+                    }
+                }
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// the names of this template's variables
+    fn variables(&self) -> Result<Vec<&str>> {
+        Ok(self.parts()?.iter().flat_map(Part::names).collect())
+    }
+
+    /// this template split into literals and expressions
+    fn parts(&self) -> Result<Vec<Part>> {
+        let mut parts = Vec::new();
+        let mut rest = self.uri_template.as_str();
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| {
+                    Error::from(
+                        format!("invalid uri template {}", self.uri_template),
+                    )
+                })?;
+            parts.push(Part::Literal(&rest[..start]));
+            let expression = &rest[start + 1..end];
+            parts.push(
+                if expression.starts_with('?') || expression.starts_with('&') {
+                    Part::Query(names(&expression[1..]))
+                } else {
+                    Part::Path(names(expression))
+                },
+            );
+            rest = &rest[end + 1..];
+        }
+        parts.push(Part::Literal(rest));
+        Ok(parts)
+    }
+}
+
+/// a piece of a URI template
+enum Part<'a> {
+    Literal(&'a str),
+    Path(Vec<&'a str>),
+    Query(Vec<&'a str>),
+}
+
+impl<'a> Part<'a> {
+    /// the names of this part's variables
+    fn names(&self) -> Vec<&'a str> {
+        match *self {
+            Part::Literal(_) => Vec::new(),
+            Part::Path(ref names) | Part::Query(ref names) => names.clone(),
+        }
+    }
+}
+
+/// the names of an expression's variables, without modifiers
+fn names(expression: &str) -> Vec<&str> {
+    expression
+        .split(',')
+        .map(|name| {
+            name.trim_end_matches('*').split(':').next().unwrap_or(name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    const HOME: &str = r#"{
+      "@type": "home",
+      "@href": "/",
+      "resources": {
+        "builds": {
+          "@type": "resource",
+          "actions": {
+            "find": [
+              {
+                "@type": "template",
+                "request_method": "GET",
+                "uri_template":
+                  "/repo/{repository.id}/builds{?include,limit,offset}"
+              },
+              {
+                "@type": "template",
+                "request_method": "GET",
+                "uri_template":
+                  "/repo/{repository.slug}/builds{?include,limit,offset}"
+              }
+            ]
+          }
+        }
+      }
+    }"#;
+
+    fn params(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn templates_are_chosen_by_their_variables() {
+        let home = serde_json::from_str::<Home>(HOME).unwrap();
+        let (method, path) = home.expand(
+            "builds",
+            "find",
+            &params(&[("repository.slug", "foo/bar"), ("limit", "5")]),
+        ).unwrap();
+        assert_eq!(method, Method::Get);
+        assert_eq!(path, "/repo/foo%2Fbar/builds?limit=5");
+        let (_, path) = home.expand(
+            "builds",
+            "find",
+            &params(&[("repository.id", "1")]),
+        ).unwrap();
+        assert_eq!(path, "/repo/1/builds");
+        assert!(home.expand("builds", "find", &params(&[])).is_err());
+    }
+
+    #[test]
+    fn unpublished_actions_are_unsupported() {
+        let home = serde_json::from_str::<Home>(HOME).unwrap();
+        match home.templates("builds", "cancel") {
+            Err(Error(ErrorKind::UnsupportedAction(ref resource, ref action),
+                      _)) => {
+                assert_eq!(
+                    (resource.as_str(), action.as_str()),
+                    ("builds", "cancel")
+                )
+            }
+            otherwise => panic!("unexpected {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn query_expressions_continue_queries() {
+        let template = Template {
+            request_method: "GET".into(),
+            uri_template: "/owner/{owner.login}/repos?sort_by=id{&limit}"
+                .into(),
+        };
+        assert_eq!(
+            template
+                .expand(&params(&[("owner.login", "softprops"),
+                                  ("limit", "a b")]))
+                .unwrap(),
+            "/owner/softprops/repos?sort_by=id&limit=a+b"
+        );
+    }
+
+    #[test]
+    fn unknown_params_are_rejected() {
+        let home = serde_json::from_str::<Home>(HOME).unwrap();
+        assert!(
+            home.expand(
+                "builds",
+                "find",
+                &params(&[("repository.slug", "foo/bar"), ("limt", "5")]),
+            ).is_err()
+        );
+        let (_, path) = home.expand(
+            "builds",
+            "find",
+            &params(&[("repository.id", "1"), ("repository.slug", "foo/bar")]),
+        ).unwrap();
+        assert_eq!(path, "/repo/1/builds");
+        let template = &home.templates("builds", "find").unwrap()[0];
+        assert!(
+            template
+                .expand(&params(&[("repository.id", "1"), ("limt", "5")]))
+                .is_err()
+        );
+    }
+}
@@ -1,9 +1,10 @@
 //! interfaces for interacting with travis envs
 
-use futures::{Future as StdFuture, IntoFuture};
+use futures::Future as StdFuture;
 use futures::future;
 
-use super::{Client, Error, Future};
+use super::{Client, Future, escape};
+use hyper::Uri;
 use hyper::client::Connect;
 use std::borrow::Cow;
 
@@ -35,11 +36,17 @@ pub struct EnvVarPatch {
 #[derive(Debug, Deserialize)]
 pub struct EnvVar {
     pub id: String,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
     pub name: Option<String>,
     pub public: Option<bool>,
     pub value: Option<String>,
     #[serde(rename = "@permissions")]
     pub permissions: EnvVarPermissions,
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,15 +59,15 @@ pub struct EnvVarPermissions {
 ///
 /// This is typicall accessed through the travis client
 /// via `travis.env("owner/repo")`
-pub struct Env<'a, C>
+pub struct Env<C>
 where
     C: Clone + Connect,
 {
-    pub(crate) travis: &'a Client<C>,
+    pub(crate) travis: Client<C>,
     pub(crate) slug: String,
 }
 
-impl<'a, C> Env<'a, C>
+impl<C> Env<C>
 where
     C: Clone + Connect,
 {
@@ -68,15 +75,7 @@ where
     pub fn vars(&self) -> Future<Vec<EnvVar>> {
         Box::new(
             self.travis
-                .get(
-                    format!(
-                        "{host}/repo/{slug}/env_vars",
-                        host = self.travis.host,
-                        slug = self.slug
-                    ).parse()
-                        .map_err(Error::from)
-                        .into_future(),
-                )
+                .get(self.locate("env_vars", "for_repository", None))
                 .and_then(
                     |wrapper: EnvVarsWrapper| future::ok(wrapper.env_vars),
                 ),
@@ -88,16 +87,7 @@ where
     where
         V: Into<Cow<'v, str>>,
     {
-        self.travis.get(
-            format!(
-                "{host}/repo/{slug}/env_var/{var_id}",
-                host = self.travis.host,
-                slug = self.slug,
-                var_id = var_id.into()
-            ).parse()
-                .map_err(Error::from)
-                .into_future(),
-        )
+        self.travis.get(self.locate("env_var", "find", Some(&var_id.into())))
     }
 
     /// updates the contents of an env var
@@ -110,30 +100,15 @@ where
         V: Into<Cow<'v, str>>,
     {
         self.travis.patch(
-            format!(
-                "{host}/repo/{slug}/env_var/{var_id}",
-                host = self.travis.host,
-                slug = self.slug,
-                var_id = var_id.into()
-            ).parse()
-                .map_err(Error::from)
-                .into_future(),
+            self.locate("env_var", "update", Some(&var_id.into())),
             options,
         )
     }
 
     /// sets a new env var for this repo
     pub fn set(&self, options: EnvVarCreate) -> Future<EnvVar> {
-        self.travis.post(
-            format!(
-                "{host}/repo/{slug}/env_vars",
-                host = self.travis.host,
-                slug = self.slug
-            ).parse()
-                .map_err(Error::from)
-                .into_future(),
-            options,
-        )
+        self.travis
+            .post(self.locate("env_vars", "create", None), options)
     }
 
     /// deletes env var
@@ -141,15 +116,33 @@ where
     where
         V: Into<Cow<'v, str>>,
     {
-        self.travis.delete(
-            format!(
-                "{host}/repo/{slug}/env_var/{var_id}",
-                host = self.travis.host,
-                slug = self.slug,
-                var_id = var_id.into()
-            ).parse()
-                .map_err(Error::from)
-                .into_future(),
-        )
+        self.travis
+            .delete(self.locate("env_var", "delete", Some(&var_id.into())))
+    }
+
+    /// the uri of this repo's env vars, or the var with `var_id`
+    fn locate(
+        &self,
+        resource: &'static str,
+        action: &'static str,
+        var_id: Option<&str>,
+    ) -> Future<Uri> {
+        let slug = escape(&self.slug);
+        match var_id {
+            Some(var_id) => self.travis.locate(
+                resource,
+                action,
+                &[("repository.slug", &self.slug), ("env_var.id", var_id)],
+                format!("/repo/{}/env_var/{}", slug, var_id),
+                String::new(),
+            ),
+            None => self.travis.locate(
+                resource,
+                action,
+                &[("repository.slug", &self.slug)],
+                format!("/repo/{}/env_vars", slug),
+                String::new(),
+            ),
+        }
     }
 }
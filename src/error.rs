@@ -1,21 +1,145 @@
 //! Travis error types
 
+use hyper::{Method, StatusCode};
 use hyper::Error as HttpError;
-use hyper::StatusCode;
 use hyper::error::UriError;
+use serde_json;
 use serde_json::error::Error as SerdeError;
+use std::fmt;
 use std::io::Error as IoError;
+use std::time::Duration;
+use url::ParseError as UrlError;
 
 #[derive(Debug, Deserialize, PartialEq)]
 pub(crate) struct ClientError {
+    #[serde(rename = "@type")]
+    pub kind: Option<String>,
     pub error_message: String,
+    pub error_type: Option<String>,
+    pub resource_type: Option<String>,
+}
+
+/// Details of a failed travis API request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fault {
+    /// http status of the response
+    pub status: StatusCode,
+    /// travis's `error_type`, like `not_found`
+    pub error_type: Option<String>,
+    /// travis's `error_message`
+    pub message: String,
+    /// the type of resource the error pertains to, like `repository`
+    pub resource_type: Option<String>,
+    /// http method of the failed request
+    pub method: Method,
+    /// url of the failed request
+    pub url: String,
+}
+
+/// An error response returned by the travis API, typed by its `error_type`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    /// the resource does not exist or is not visible to the
+    /// authenticated user
+    NotFound(Fault),
+    /// the authenticated user may not perform this operation
+    InsufficientAccess(Fault),
+    /// the request's parameters were rejected
+    WrongParams(Fault),
+    /// the resource being created already exists
+    DuplicateResource(Fault),
+    /// the operation requires authentication
+    LoginRequired(Fault),
+    /// the provided credentials were rejected
+    WrongCredentials(Fault),
+    /// any other error
+    Other(Fault),
+}
+
+impl ApiError {
+    /// classifies a fault by its `error_type`, falling back on its status
+    /// when travis didn't provide one
+    pub fn new(fault: Fault) -> Self {
+        let error_type = match fault.error_type {
+            Some(ref error_type) => error_type.clone(),
+            None => {
+                match fault.status {
+                    StatusCode::NotFound => "not_found",
+                    StatusCode::Forbidden => "insufficient_access",
+                    StatusCode::Unauthorized => "login_required",
+                    StatusCode::BadRequest |
+                    StatusCode::UnprocessableEntity => "wrong_params",
+                    StatusCode::Conflict => "duplicate_resource",
+                    _ => "",
+                }.into()
+            }
+        };
+        match error_type.as_ref() {
+            "not_found" => ApiError::NotFound(fault),
+            "insufficient_access" => ApiError::InsufficientAccess(fault),
+            "wrong_params" => ApiError::WrongParams(fault),
+            "duplicate_resource" => ApiError::DuplicateResource(fault),
+            "login_required" => ApiError::LoginRequired(fault),
+            "wrong_credentials" => ApiError::WrongCredentials(fault),
+            _ => ApiError::Other(fault),
+        }
+    }
+
+    /// details of the failed request
+    pub fn fault(&self) -> &Fault {
+        match *self {
+            ApiError::NotFound(ref fault) |
+            ApiError::InsufficientAccess(ref fault) |
+            ApiError::WrongParams(ref fault) |
+            ApiError::DuplicateResource(ref fault) |
+            ApiError::LoginRequired(ref fault) |
+            ApiError::WrongCredentials(ref fault) |
+            ApiError::Other(ref fault) => fault,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fault = self.fault();
+        write!(
+            f,
+            "{} {} failed with {}: {}",
+            fault.method,
+            fault.url,
+            fault.status,
+            fault.message
+        )
+    }
 }
 
 error_chain! {
     errors {
-        Fault {
-            code: StatusCode,
-            error: String,
+        Api(error: ApiError) {
+            description("travis api error")
+            display("{}", error)
+        }
+        RateLimited {
+            retry_after: Option<Duration>,
+        } {
+            description("rate limited")
+            display("rate limited, retry after {:?}", retry_after)
+        }
+        Exhausted(attempts: u32) {
+            description("request retries exhausted")
+            display("request failed after {} attempts", attempts)
+        }
+        Stopped {
+            description("client stopped")
+            display("the reactor running the remote client stopped")
+        }
+        UnsupportedAction(resource: String, action: String) {
+            description("unsupported action")
+            display("travis does not support {}.{}", resource, action)
+        }
+        Unmatched(method: String, url: String) {
+            description("no recorded interaction")
+            display("no recorded interaction for {} {}", method, url)
         }
     }
     foreign_links {
@@ -23,5 +147,121 @@ error_chain! {
         Http(HttpError);
         IO(IoError);
         Uri(UriError);
+        Url(UrlError);
+    }
+}
+
+impl Error {
+    /// The number of attempts made, when retries ran out before this error
+    ///
+    /// Exhausted retries keep the last attempt's error, with
+    /// `ErrorKind::Exhausted` chained as its cause.
+    pub fn attempts(&self) -> Option<u32> {
+        let cause = self.1.next_error.as_ref().and_then(
+            |cause| cause.downcast_ref::<Error>(),
+        );
+        match cause {
+            Some(&Error(ErrorKind::Exhausted(attempts), _)) => Some(attempts),
+            _ => None,
+        }
+    }
+
+    /// chains `Exhausted(attempts)` between this error and its cause
+    pub(crate) fn exhausted(self, attempts: u32) -> Self {
+        let Error(kind, mut state) = self;
+        let mut exhausted = Error::from(ErrorKind::Exhausted(attempts));
+        exhausted.1.next_error = state.next_error.take();
+        state.next_error = Some(Box::new(exhausted));
+        Error(kind, state)
+    }
+}
+
+/// converts an unsuccessful response body into an error
+///
+/// Bodies which aren't travis error representations, like the html pages
+/// proxies serve with 5xx responses, are kept as the error's message
+pub(crate) fn api_error(
+    status: StatusCode,
+    method: Method,
+    url: String,
+    body: &[u8],
+) -> Error {
+    let fault = match serde_json::from_slice::<ClientError>(body) {
+        Ok(error) => Fault {
+            status,
+            error_type: error.error_type,
+            message: error.error_message,
+            resource_type: error.resource_type,
+            method,
+            url,
+        },
+        Err(_) => Fault {
+            status,
+            error_type: None,
+            message: String::from_utf8_lossy(body).into_owned(),
+            resource_type: None,
+            method,
+            url,
+        },
+    };
+    ErrorKind::Api(ApiError::new(fault)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_errors_are_typed() {
+        let error = api_error(
+            StatusCode::NotFound,
+            Method::Get,
+            "https://api.travis-ci.org/repo/foo%2Fbar".into(),
+            br#"{
+              "@type": "error",
+              "error_type": "not_found",
+              "error_message": "repository not found (or insufficient access)",
+              "resource_type": "repository"
+            }"#,
+        );
+        match *error.kind() {
+            ErrorKind::Api(ApiError::NotFound(ref fault)) => {
+                assert_eq!(fault.resource_type, Some("repository".into()));
+                assert_eq!(fault.method, Method::Get);
+            }
+            ref otherwise => panic!("unexpected {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn api_errors_fall_back_on_status() {
+        let error = api_error(
+            StatusCode::Forbidden,
+            Method::Delete,
+            "https://api.travis-ci.org/repo/foo%2Fbar/env_var/1".into(),
+            br#"{"error_message": "forbidden"}"#,
+        );
+        match *error.kind() {
+            ErrorKind::Api(ApiError::InsufficientAccess(ref fault)) => {
+                assert_eq!(fault.message, "forbidden")
+            }
+            ref otherwise => panic!("unexpected {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn unstructured_bodies_become_messages() {
+        let error = api_error(
+            StatusCode::BadGateway,
+            Method::Delete,
+            "https://api.travis-ci.org/repo/foo%2Fbar/env_var/1".into(),
+            b"<html>bad gateway</html>",
+        );
+        match *error.kind() {
+            ErrorKind::Api(ApiError::Other(ref fault)) => {
+                assert_eq!(fault.message, "<html>bad gateway</html>")
+            }
+            ref otherwise => panic!("unexpected {:?}", otherwise),
+        }
     }
 }
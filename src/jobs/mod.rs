@@ -1,7 +1,7 @@
 //! interfaces for interacting with travis jobs
 
-use super::{Client, Error, Future, Owner, State};
-use futures::{Future as StdFuture, IntoFuture};
+use super::{Client, Future, Minimal, Owner, State, Timestamp};
+use futures::Future as StdFuture;
 use futures::future;
 use hyper::client::Connect;
 
@@ -10,47 +10,139 @@ struct JobsWrapper {
     jobs: Vec<Job>,
 }
 
+/// A job's minimal representation, as embedded in other resources
+#[derive(Debug, Deserialize, Clone)]
+pub struct MinimalJob {
+    pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
+}
+
+impl Minimal for MinimalJob {
+    type Standard = Job;
+
+    const STANDARD_FIELDS: &'static [&'static str] = &[
+        "number",
+        "state",
+        "created_at",
+        "started_at",
+        "finished_at",
+        "queue",
+        "owner",
+    ];
+
+    fn path(&self) -> String {
+        self.href.clone().unwrap_or_else(|| format!("/job/{}", self.id))
+    }
+}
+
+/// A job's standard representation
 #[derive(Debug, Deserialize, Clone)]
 pub struct Job {
     pub id: usize,
-    // standard rep fields
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
     pub number: Option<String>,
     pub state: Option<State>,
-    pub started_at: Option<String>,
-    pub finished_at: Option<String>,
-    //pub build:
+    #[serde(default)]
+    pub created_at: Option<Timestamp>,
+    pub started_at: Option<Timestamp>,
+    pub finished_at: Option<Timestamp>,
+    /// the queue the job was routed to, once it has been
     pub queue: Option<String>,
-    //pub repository
-    // pub commit
     pub owner: Option<Owner>,
-    //pub stage
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
+}
+
+#[cfg(feature = "chrono")]
+impl Job {
+    /// time spent waiting between creation and starting
+    pub fn queue_time(&self) -> Option<::std::time::Duration> {
+        ::between(&self.created_at, &self.started_at)
+    }
+
+    /// time elapsed between starting and finishing
+    pub fn elapsed(&self) -> Option<::std::time::Duration> {
+        ::between(&self.started_at, &self.finished_at)
+    }
 }
 
-pub struct Jobs<'a, C>
+pub struct Jobs<C>
 where
     C: Clone + Connect,
 {
-    pub(crate) travis: &'a Client<C>,
+    pub(crate) travis: Client<C>,
     pub(crate) build_id: usize,
 }
 
-impl<'a, C> Jobs<'a, C>
+impl<C> Jobs<C>
 where
     C: Clone + Connect,
 {
     pub fn list(&self) -> Future<Vec<Job>> {
         Box::new(
             self.travis
-                .get(
-                    format!(
-                        "{host}/build/{build_id}/jobs",
-                        host = self.travis.host,
-                        build_id = self.build_id
-                    ).parse()
-                        .map_err(Error::from)
-                        .into_future(),
-                )
+                .get(self.travis.locate(
+                    "jobs",
+                    "find",
+                    &[("build.id", &self.build_id.to_string())],
+                    format!("/build/{}/jobs", self.build_id),
+                    String::new(),
+                ))
                 .and_then(|wrapper: JobsWrapper| future::ok(wrapper.jobs)),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    #[cfg(feature = "chrono")]
+    fn job_durations() {
+        use std::time::Duration;
+        let job = serde_json::from_str::<Job>(
+            r#"{
+              "id": 1, "number": "1.1", "state": "passed",
+              "owner": {"id": 2, "login": "softprops"},
+              "created_at": "2017-06-27T14:02:00Z",
+              "started_at": "2017-06-27T14:02:30Z",
+              "finished_at": "2017-06-27T14:05:30Z"
+            }"#,
+        ).unwrap();
+        assert_eq!(job.queue_time(), Some(Duration::from_secs(30)));
+        assert_eq!(job.elapsed(), Some(Duration::from_secs(180)));
+    }
+
+    #[test]
+    #[cfg(not(feature = "chrono"))]
+    fn job_timestamps_are_strings() {
+        let job = serde_json::from_str::<Job>(
+            r#"{"id": 1, "number": "1.1", "state": "started",
+            "owner": {"id": 2, "login": "softprops"},
+            "started_at": "2017-06-27T14:02:30Z"}"#,
+        ).unwrap();
+        assert_eq!(job.started_at, Some("2017-06-27T14:02:30Z".to_owned()));
+    }
+
+    #[test]
+    #[cfg(feature = "extra-fields")]
+    fn job_captures_unmodeled_fields() {
+        let job = serde_json::from_str::<Job>(
+            r#"{"id": 1, "number": "1.1", "state": "booting",
+            "owner": {"id": 2, "login": "softprops"},
+            "allow_failure": true}"#,
+        ).unwrap();
+        assert_eq!(job.state, Some(State::Unknown("booting".into())));
+        assert_eq!(job.extra["allow_failure"], serde_json::Value::Bool(true));
+    }
+}
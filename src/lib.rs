@@ -31,9 +31,25 @@
 //!
 //! # Cargo features
 //!
-//! This crate has one Cargo feature, `tls`,
-//! which adds HTTPS support via the `Client::{oss,pro}`
+//! The `tls` feature adds HTTPS support via the `Client::{oss,pro}`
 //! constructors. This feature is enabled by default.
+//!
+//! The `rustls-tls` feature provides the same constructors over
+//! [rustls](https://github.com/ctz/rustls) rather than the platform's tls
+//! library, for builds which can't link OpenSSL, like static musl builds.
+//! Disable default features when enabling it; `tls` takes precedence.
+//!
+//! The `chrono` feature deserializes timestamps as `chrono::DateTime<Utc>`
+//! values rather than raw ISO-8601 strings and adds duration helpers like
+//! `Build::queue_time` and `Job::elapsed`.
+//!
+//! The `extra-fields` feature captures fields travis returns which this
+//! crate doesn't model yet in an `extra` map on `Build`, `Job`,
+//! `Repository` and `EnvVar`.
+//!
+//! The `test-server` feature adds `server::Server`, a stateful fake of the
+//! travis API for integration tests, whose builds move through scripted
+//! states.
 #[deny(missing_docs)]
 #[macro_use]
 extern crate derive_builder;
@@ -45,29 +61,46 @@ extern crate log;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[cfg_attr(any(test, feature = "test-server"), macro_use)]
 extern crate serde_json;
+extern crate serde_yaml;
+extern crate rand;
 extern crate tokio_core;
+extern crate tokio_io;
 extern crate url;
 #[macro_use]
 extern crate error_chain;
 #[cfg(feature = "tls")]
 extern crate hyper_tls;
-
 #[cfg(feature = "tls")]
-use hyper_tls::HttpsConnector;
+extern crate native_tls;
+#[cfg(feature = "rustls-tls")]
+extern crate hyper_rustls;
+#[cfg(feature = "rustls-tls")]
+extern crate rustls;
+#[cfg(feature = "rustls-tls")]
+extern crate webpki_roots;
+#[cfg(feature = "chrono")]
+extern crate chrono;
 
-use futures::{Future as StdFuture, IntoFuture, Stream as StdStream, stream};
-use futures::future::FutureResult;
+use futures::{Future as StdFuture, IntoFuture, future, stream};
 use std::borrow::Cow;
 
-use hyper::{Client as HyperClient, Method, Request, StatusCode, Uri};
-use hyper::client::{Connect, HttpConnector};
-use hyper::header::{Accept, Authorization, ContentType, UserAgent};
+use hyper::{Client as HyperClient, Method, StatusCode, Uri};
+use hyper::client::Connect;
+use hyper::header::{Accept, Authorization, ContentType, Headers, UserAgent};
 
-use serde::de::DeserializeOwned;
+use serde::de::{Deserialize, DeserializeOwned, Deserializer, Error as DeError};
 use serde::ser::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt;
-use tokio_core::reactor::Core;
+use std::result;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio_core::reactor::{Core, Handle, Timeout};
 use url::percent_encoding::{PATH_SEGMENT_ENCODE_SET, utf8_percent_encode};
 
 pub mod env;
@@ -78,7 +111,34 @@ pub mod jobs;
 use jobs::Jobs;
 pub mod repos;
 use repos::Repos;
+pub mod pagination;
+use pagination::{PageOptions, Paginated};
+pub mod retry;
+pub mod transport;
+pub mod cache;
+pub mod discovery;
+use discovery::Home;
+use cache::{Cache, Store};
+#[cfg(feature = "test-server")]
+pub mod server;
+use transport::{Layer, NoConnector, Transport};
+pub mod remote;
+pub use remote::RemoteClient;
+pub mod blocking;
+mod logging;
+use retry::RetryPolicy;
+pub mod rate_limit;
+use rate_limit::{RateLimit, Slot, Slots, Throttle};
+#[cfg(test)]
+mod testing;
 
+pub mod connect;
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+use connect::DefaultConnector;
+pub mod builder;
+pub use builder::ClientBuilder;
+pub mod credential;
+pub use credential::{Credential, CredentialSource};
 pub mod error;
 use error::*;
 pub use error::{Error, Result};
@@ -88,12 +148,13 @@ header! {
     (TravisApiVersion, "Travis-Api-Version") => [String]
 }
 
-const OSS_HOST: &str = "https://api.travis-ci.org";
-const PRO_HOST: &str = "https://api.travis-ci.com";
+/// Travis API host for open source repository builds
+pub const OSS_HOST: &str = "https://api.travis-ci.org";
+/// Travis API host for private repository builds
+pub const PRO_HOST: &str = "https://api.travis-ci.com";
 
 /// Enumeration of Travis Build/Job states
-#[derive(Debug, Deserialize, Clone, PartialEq)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq)]
 pub enum State {
     /// Workload was received and machine is booting
     Received,
@@ -109,6 +170,26 @@ pub enum State {
     Failed,
     /// Travis build errored
     Errored,
+    /// A state this crate doesn't know about yet
+    Unknown(String),
+}
+
+impl<'de> Deserialize<'de> for State {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match String::deserialize(deserializer)?.as_ref() {
+            "received" => State::Received,
+            "created" => State::Created,
+            "started" => State::Started,
+            "canceled" => State::Canceled,
+            "passed" => State::Passed,
+            "failed" => State::Failed,
+            "errored" => State::Errored,
+            other => State::Unknown(other.to_owned()),
+        })
+    }
 }
 
 impl fmt::Display for State {
@@ -124,40 +205,26 @@ impl fmt::Display for State {
                 State::Passed => "passed",
                 State::Failed => "failed",
                 State::Errored => "errored",
+                State::Unknown(ref state) => state,
             }
         )
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
-struct Pagination {
-    count: usize,
-    first: Page,
-    next: Option<Page>,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-struct Page {
-    #[serde(rename = "@href")]
-    href: String,
-}
+/// Fields returned by travis which this crate doesn't model yet
+///
+/// Models expose these as an `extra` field when the `extra-fields`
+/// feature is enabled
+pub type Extra = BTreeMap<String, serde_json::Value>;
 
-/// Representation of types of API credentials used to authenticate the client
-#[derive(Clone, Debug)]
-pub enum Credential {
-    /// A Travis API token
-    ///
-    /// Typically obtained from `travis token` ruby cli
-    Token(String),
-    /// A Github API token
-    ///
-    /// This will be immediately exchanged for a travis token
-    /// after constructing a `travis::Client` instance.
-    /// Care should be taken to associate appropriate
-    /// [Github scopes](https://docs.travis-ci.com/user/github-oauth-scopes/)
-    /// with these tokens to perform target operations for on oss vs private
-    /// repositories
-    Github(String),
+/// Credentials shared between clones of a client
+#[derive(Debug, Default)]
+struct Auth {
+    /// the travis token sent with requests
+    token: Option<String>,
+    /// the github token `token` was exchanged for, kept so the exchange
+    /// can be repeated when travis stops accepting `token`
+    github: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -174,51 +241,206 @@ struct AccessToken {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Branch {
     pub name: String,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
 }
 
 /// A Github owner
 #[derive(Debug, Deserialize, Clone)]
 pub struct Owner {
     pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
     pub login: String,
 }
 
+/// A git commit
+#[derive(Debug, Deserialize, Clone)]
+pub struct Commit {
+    pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
+    pub sha: Option<String>,
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub message: Option<String>,
+    pub compare_url: Option<String>,
+    pub committed_at: Option<Timestamp>,
+}
+
+/// A minimal representation of a resource, as travis embeds in others
+///
+/// Minimal representations carry little more than an id. Their standard
+/// representations are loaded with `Client::fetch`.
+pub trait Minimal {
+    /// the resource's standard representation
+    type Standard: DeserializeOwned + 'static;
+
+    /// fields only the standard representation has, telling the two apart
+    /// when travis doesn't label them
+    const STANDARD_FIELDS: &'static [&'static str];
+
+    /// the path of the resource's standard representation
+    fn path(&self) -> String;
+}
+
+/// A resource embedded in another, in whichever representation travis
+/// returned
+///
+/// Travis embeds minimal representations unless the standard ones were
+/// requested with an include, like `builds::Include::Jobs`.
+/// `Client::standard` resolves either to the standard representation.
+/// Resources without an `@representation` are standard if they have any
+/// field only the standard representation has, and minimal otherwise.
+///
+/// `Branch`, `Owner` and `Commit` aren't wrapped: they model only fields
+/// of the minimal representation, which the standard one shares, so
+/// either deserializes without loss.
+#[derive(Debug, Clone)]
+pub enum Embedded<M, S> {
+    Minimal(M),
+    Standard(Box<S>),
+}
+
+impl<M, S> Embedded<M, S> {
+    /// the standard representation, if travis embedded it
+    pub fn standard(&self) -> Option<&S> {
+        match *self {
+            Embedded::Standard(ref standard) => Some(standard),
+            Embedded::Minimal(_) => None,
+        }
+    }
+
+    /// consumes this resource, yielding its standard representation if
+    /// travis embedded it
+    pub fn into_standard(self) -> Option<S> {
+        match self {
+            Embedded::Standard(standard) => Some(*standard),
+            Embedded::Minimal(_) => None,
+        }
+    }
+}
+
+impl<'de, M, S> Deserialize<'de> for Embedded<M, S>
+where
+    M: Minimal<Standard = S> + DeserializeOwned,
+    S: DeserializeOwned + 'static,
+{
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let standard = match value
+            .get("@representation")
+            .and_then(|r| r.as_str()) {
+            Some(representation) => representation == "standard",
+            None => M::STANDARD_FIELDS.iter().any(
+                |field| value.get(field).is_some(),
+            ),
+        };
+        if standard {
+            serde_json::from_value(value).map(|standard| {
+                Embedded::Standard(Box::new(standard))
+            })
+        } else {
+            serde_json::from_value(value).map(Embedded::Minimal)
+        }.map_err(D::Error::custom)
+    }
+}
+
+/// A point in time reported by travis
+///
+/// With the `chrono` feature enabled this is a `chrono::DateTime<Utc>`,
+/// otherwise it's the raw ISO-8601 string
+#[cfg(feature = "chrono")]
+pub type Timestamp = chrono::DateTime<chrono::Utc>;
+
+/// A point in time reported by travis
+///
+/// With the `chrono` feature enabled this is a `chrono::DateTime<Utc>`,
+/// otherwise it's the raw ISO-8601 string
+#[cfg(not(feature = "chrono"))]
+pub type Timestamp = String;
+
+/// time elapsed between two optional timestamps
+#[cfg(feature = "chrono")]
+pub(crate) fn between(
+    start: &Option<Timestamp>,
+    end: &Option<Timestamp>,
+) -> Option<std::time::Duration> {
+    match (start, end) {
+        (&Some(ref start), &Some(ref end)) => {
+            end.signed_duration_since(*start).to_std().ok()
+        }
+        _ => None,
+    }
+}
+
 /// A type alias for `Futures` that may return `travis::Errors`
 pub type Future<T> = Box<StdFuture<Item = T, Error = Error>>;
 
 /// A type alias for `Streams` that may result in `travis::Errors`
 pub type Stream<T> = Box<stream::Stream<Item = T, Error = Error>>;
 
+/// A `Send` variant of `Future`, resolved by a `RemoteClient`
+pub type SendFuture<T> = Box<StdFuture<Item = T, Error = Error> + Send>;
+
+/// A `Send` variant of `Stream`, produced by a `RemoteClient`
+pub type SendStream<T> = Box<stream::Stream<Item = T, Error = Error> + Send>;
+
 pub(crate) fn escape(raw: &str) -> String {
     utf8_percent_encode(raw, PATH_SEGMENT_ENCODE_SET).to_string()
 }
 
+/// owned copies of action params
+fn parameters(params: &[(&str, &str)]) -> BTreeMap<String, String> {
+    params
+        .iter()
+        .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
 /// Entry point for all travis operations
 ///
 /// Instances of Clients may be cloned.
-#[derive(Clone, Debug)]
+///
+/// `C` is the connector of the hyper client a Client was created with.
+/// Clients created with other transports are
+/// `Client<transport::NoConnector>`s.
+#[derive(Clone)]
 pub struct Client<C>
 where
     C: Clone + Connect,
 {
-    http: HyperClient<C>,
-    credential: Option<Credential>,
+    transport: Rc<Transport>,
+    handle: Handle,
+    connector: PhantomData<C>,
+    auth: Arc<RwLock<Auth>>,
     host: String,
+    retry: RetryPolicy,
+    throttle: Option<Throttle>,
+    slots: Option<Slots>,
+    rate_limit: Arc<Mutex<Option<RateLimit>>>,
+    timeout: Option<Duration>,
+    user_agent: String,
+    headers: Headers,
+    log_secrets: bool,
+    /// whether typed resources are addressed by the home document
+    discovery: bool,
+    /// the home document, once discovered
+    home: Rc<RefCell<Option<Rc<Home>>>>,
 }
 
-#[cfg(feature = "tls")]
-impl Client<HttpsConnector<HttpConnector>> {
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+impl Client<DefaultConnector> {
     /// Creates an Travis client for open source github repository builds
     pub fn oss(
         credential: Option<Credential>,
         core: &mut Core,
     ) -> Result<Self> {
-        let connector = HttpsConnector::new(4, &core.handle()).unwrap();
-        let http = HyperClient::configure()
-            .connector(connector)
-            .keep_alive(true)
-            .build(&core.handle());
-        Client::custom(OSS_HOST, http, credential, core)
+        let client = Client::oss_async(credential, &core.handle());
+        core.run(client)
     }
 
     /// Creates a Travis client for private github repository builds
@@ -226,12 +448,38 @@ impl Client<HttpsConnector<HttpConnector>> {
         credential: Option<Credential>,
         core: &mut Core,
     ) -> Result<Self> {
-        let connector = HttpsConnector::new(4, &core.handle()).unwrap();
-        let http = HyperClient::configure()
-            .connector(connector)
-            .keep_alive(true)
-            .build(&core.handle());
-        Client::custom(PRO_HOST, http, credential, core)
+        let client = Client::pro_async(credential, &core.handle());
+        core.run(client)
+    }
+
+    /// Creates an Travis client for open source github repository builds
+    /// without blocking on credential exchange
+    pub fn oss_async(
+        credential: Option<Credential>,
+        handle: &Handle,
+    ) -> Future<Self> {
+        authenticated(ClientBuilder::new(), credential).build_async(handle)
+    }
+
+    /// Creates a Travis client for private github repository builds
+    /// without blocking on credential exchange
+    pub fn pro_async(
+        credential: Option<Credential>,
+        handle: &Handle,
+    ) -> Future<Self> {
+        authenticated(ClientBuilder::new().host(PRO_HOST), credential)
+            .build_async(handle)
+    }
+}
+
+#[cfg(any(feature = "tls", feature = "rustls-tls"))]
+fn authenticated(
+    builder: ClientBuilder,
+    credential: Option<Credential>,
+) -> ClientBuilder {
+    match credential {
+        Some(credential) => builder.credential(credential),
+        None => builder,
     }
 }
 
@@ -240,6 +488,10 @@ where
     C: Clone + Connect,
 {
     /// Creates a Travis client for hosted versions of travis
+    ///
+    /// This blocks on `core` to exchange `Credential::Github` credentials
+    /// for a travis token. See `Client::custom_async` to construct clients
+    /// from within running futures.
     pub fn custom<H>(
         host: H,
         http: HyperClient<C>,
@@ -249,97 +501,204 @@ where
     where
         H: Into<String>,
     {
+        let handle = core.handle();
+        core.run(Client::custom_async(host, http, credential, &handle))
+    }
+
+    /// Creates a Travis client for hosted versions of travis, resolving
+    /// once `Credential::Github` credentials are exchanged for a travis token
+    ///
+    /// `handle` drives timeouts, retries and throttling
+    pub fn custom_async<H>(
+        host: H,
+        http: HyperClient<C>,
+        credential: Option<Credential>,
+        handle: &Handle,
+    ) -> Future<Self>
+    where
+        H: Into<String>,
+    {
+        Client::new(host, http, handle).authenticate(credential)
+    }
+
+    /// an unauthenticated client with default settings
+    fn new<H>(host: H, http: HyperClient<C>, handle: &Handle) -> Self
+    where
+        H: Into<String>,
+    {
+        Self::with_transport(host, Rc::new(http), handle.clone())
+    }
+
+    fn with_transport<H>(
+        host: H,
+        transport: Rc<Transport>,
+        handle: Handle,
+    ) -> Self
+    where
+        H: Into<String>,
+    {
+        Self {
+            transport,
+            handle,
+            connector: PhantomData,
+            auth: Default::default(),
+            host: host.into(),
+            retry: RetryPolicy::none(),
+            throttle: None,
+            slots: None,
+            rate_limit: Default::default(),
+            timeout: None,
+            user_agent: format!("Travis/{}", env!("CARGO_PKG_VERSION")),
+            headers: Headers::new(),
+            log_secrets: false,
+            discovery: false,
+            home: Default::default(),
+        }
+    }
+
+    /// resolves to this client once `credential` is usable, exchanging
+    /// github tokens for travis tokens
+    fn authenticate(self, credential: Option<Credential>) -> Future<Self> {
         match credential {
             Some(Credential::Github(gh)) => {
-                // exchange github token for travis token
-                let host = host.into();
-                let http_client = http.clone();
-                let uri = format!("{host}/auth/github", host = host)
-                    .parse()
-                    .map_err(Error::from)
-                    .into_future();
-                let response = uri.and_then(move |uri| {
-                    let mut req = Request::new(Method::Post, uri);
-                    {
-                        let mut headers = req.headers_mut();
-                        headers.set(UserAgent::new(
-                            format!("Travis/{}", env!("CARGO_PKG_VERSION")),
-                        ));
-                        headers.set(Accept(vec![
-                            "application/vnd.travis-ci.2+json"
-                                .parse()
-                                .unwrap(),
-                        ]));
-                        headers.set(ContentType::json());
-                    }
-                    req.set_body(
-                        serde_json::to_vec(
-                            &GithubToken { github_token: gh.to_owned() },
-                        ).unwrap(),
-                    );
-                    http_client.request(req).map_err(Error::from)
-                });
-
-                let parse = response.and_then(move |response| {
-                    let status = response.status();
-                    let body = response.body().concat2().map_err(Error::from);
-                    body.and_then(move |body| if status.is_success() {
-                        debug!(
-                            "body {}",
-                            ::std::str::from_utf8(&body).unwrap()
-                        );
-                        serde_json::from_slice::<AccessToken>(&body).map_err(
-                            |error| {
-                                ErrorKind::Codec(error).into()
-                            },
-                        )
-                    } else {
-                        if StatusCode::Forbidden == status {
-                            return Err(
-                                ErrorKind::Fault {
-                                    code: status,
-                                    error: String::from_utf8_lossy(&body)
-                                        .into_owned()
-                                        .clone(),
-                                }.into(),
-                            );
-                        }
-                        debug!(
-                            "{} err {}",
-                            status,
-                            ::std::str::from_utf8(&body).unwrap()
-                        );
-                        match serde_json::from_slice::<ClientError>(&body) {
-                            Ok(error) => Err(
-                                ErrorKind::Fault {
-                                    code: status,
-                                    error: error.error_message,
-                                }.into(),
-                            ),
-                            Err(error) => Err(ErrorKind::Codec(error).into()),
-                        }
-                    })
-                });
-                let client = parse.map(move |access| {
-                    Self {
-                        http,
-                        credential: Some(Credential::Token(
-                            access.access_token.to_owned(),
-                        )),
-                        host: host.into(),
-                    }
-                });
-
-                core.run(client)
+                Box::new(self.exchange(gh.clone()).map(move |token| {
+                    *self.auth.write().unwrap() = Auth {
+                        token: Some(token),
+                        github: Some(gh),
+                    };
+                    self
+                }))
             }
-            _ => Ok(Self {
-                http,
-                credential,
-                host: host.into(),
-            }),
+            Some(Credential::Token(token)) => {
+                self.auth.write().unwrap().token = Some(token);
+                Box::new(future::ok(self))
+            }
+            None => Box::new(future::ok(self)),
+        }
+    }
+
+    /// exchanges a github token for a travis token
+    ///
+    /// Exchanges are sent like other requests, subject to this client's
+    /// timeout, retry policy, throttle and default headers
+    fn exchange(&self, github_token: String) -> Future<String> {
+        let url = format!("{host}/auth/github", host = self.host);
+        let mut headers = self.headers();
+        headers.set(Accept(
+            vec!["application/vnd.travis-ci.2+json".parse().unwrap()],
+        ));
+        headers.set(ContentType::json());
+        let request = transport::Request {
+            method: Method::Post,
+            url: url.clone(),
+            headers,
+            body: Some(
+                serde_json::to_vec(&GithubToken { github_token }).unwrap(),
+            ),
+        };
+        let client = self.clone();
+        let exchange = retry::retry(
+            self.retry.clone(),
+            self.handle.clone(),
+            Method::Post,
+            url,
+            move || client.dispatch(request.clone()),
+        ).and_then(|response| {
+            serde_json::from_slice::<AccessToken>(&response.body)
+                .map(|access| access.access_token)
+                .map_err(|error| ErrorKind::Codec(error).into())
+        });
+        Box::new(exchange)
+    }
+
+    /// Sends requests through `layer`, which wraps this client's current
+    /// transport with middleware
+    pub fn with_layer<L>(self, layer: L) -> Self
+    where
+        L: Layer,
+    {
+        Self {
+            transport: layer.layer(self.transport.clone()),
+            ..self
+        }
+    }
+
+    /// Caches `GET` responses in `store`, revalidating them with
+    /// conditional requests. See the `cache` module
+    ///
+    /// By default clients don't cache responses
+    pub fn with_cache<S>(self, store: S) -> Self
+    where
+        S: Store + 'static,
+    {
+        self.with_layer(Cache::new(store))
+    }
+
+    /// Retries requests which fail for transient reasons as described by
+    /// `policy`
+    ///
+    /// By default clients don't retry requests
+    pub fn with_retry_policy(self, policy: RetryPolicy) -> Self {
+        Self {
+            retry: policy,
+            ..self
+        }
+    }
+
+    /// Delays requests as needed to stay within `throttle`'s rate
+    ///
+    /// By default clients don't throttle requests
+    pub fn with_throttle(self, throttle: Throttle) -> Self {
+        Self {
+            throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Sends at most `size` requests at once, bounding the connections
+    /// this client and its clones open
+    ///
+    /// Further requests wait for one in flight to finish. By default
+    /// requests aren't bounded
+    pub fn with_pool_size(self, size: usize) -> Self {
+        Self {
+            slots: Some(Slots::new(size)),
+            ..self
+        }
+    }
+
+    /// Logs credentials, tokens and env var values along with requests and
+    /// responses
+    ///
+    /// By default these are redacted from logs. This is unsafe anywhere
+    /// logs may be read by others, like CI build logs, and is only meant
+    /// for debugging this crate
+    pub fn dangerously_log_secrets(self) -> Self {
+        Self {
+            log_secrets: true,
+            ..self
+        }
+    }
+
+    /// Addresses typed resources, like `env` and `builds`, by the actions
+    /// published in the home document rather than built-in paths
+    ///
+    /// Built-in paths are still used for actions travis doesn't publish,
+    /// or when the home document can't be loaded. See the `discovery`
+    /// module
+    pub fn with_discovery(self) -> Self {
+        Self {
+            discovery: true,
+            ..self
         }
     }
 
+    /// The rate limit status reported with the most recent response,
+    /// if travis reported one
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limit.lock().unwrap().clone()
+    }
+
     /// get a list of repos for the a given owner (user or org)
     pub fn repos(&self) -> Repos<C> {
         Repos { travis: self.clone() }
@@ -351,8 +710,8 @@ where
         R: Into<Cow<'a, str>>,
     {
         Env {
-            travis: &self,
-            slug: escape(slug.into().as_ref()),
+            travis: self.clone(),
+            slug: slug.into().into_owned(),
         }
     }
 
@@ -363,130 +722,1026 @@ where
     {
         Builds {
             travis: self.clone(),
-            slug: escape(slug.into().as_ref()),
+            slug: slug.into().into_owned(),
         }
     }
 
     /// get a ref to jobs associated with a build
     pub fn jobs(&self, build_id: usize) -> Jobs<C> {
         Jobs {
-            travis: &self,
+            travis: self.clone(),
             build_id: build_id,
         }
     }
 
-    pub(crate) fn patch<T, B>(
+    /// Fetches the standard representation of a minimal resource
+    pub fn fetch<M>(&self, minimal: &M) -> Future<M::Standard>
+    where
+        M: Minimal,
+    {
+        self.follow(&minimal.path())
+    }
+
+    /// Resolves to the standard representation of an embedded resource,
+    /// fetching it only when travis embedded the minimal one
+    pub fn standard<M>(
+        &self,
+        embedded: Embedded<M, M::Standard>,
+    ) -> Future<M::Standard>
+    where
+        M: Minimal,
+    {
+        match embedded {
+            Embedded::Standard(standard) => Box::new(future::ok(*standard)),
+            Embedded::Minimal(minimal) => self.fetch(&minimal),
+        }
+    }
+
+    /// The home document describing the resources and actions travis
+    /// supports, fetched once and shared between clones of this client
+    pub fn home(&self) -> Future<Rc<Home>> {
+        if let Some(ref home) = *self.home.borrow() {
+            return Box::new(future::ok(home.clone()));
+        }
+        let discovered = self.home.clone();
+        Box::new(
+            self.get::<Home, _>(
+                format!("{host}/", host = self.host)
+                    .parse()
+                    .map_err(Error::from)
+                    .into_future(),
+            ).map(move |home| {
+                let home = Rc::new(home);
+                *discovered.borrow_mut() = Some(home.clone());
+                home
+            }),
+        )
+    }
+
+    /// Performs `resource`'s `action` as published in the home document,
+    /// expanding its URI template with `params`
+    ///
+    /// Fails with `ErrorKind::UnsupportedAction` when travis doesn't
+    /// publish the action. See the `discovery` module
+    pub fn action<T>(
+        &self,
+        resource: &str,
+        action: &str,
+        params: &[(&str, &str)],
+    ) -> Future<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.act(resource, action, params, None)
+    }
+
+    /// Like `action`, sending `body` as the action's parameters
+    pub fn action_with<T, B>(
+        &self,
+        resource: &str,
+        action: &str,
+        params: &[(&str, &str)],
+        body: B,
+    ) -> Future<T>
+    where
+        T: DeserializeOwned + 'static,
+        B: Serialize,
+    {
+        self.act(
+            resource,
+            action,
+            params,
+            Some(serde_json::to_vec(&body).unwrap()),
+        )
+    }
+
+    fn act<T>(
+        &self,
+        resource: &str,
+        action: &str,
+        params: &[(&str, &str)],
+        body: Option<Vec<u8>>,
+    ) -> Future<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let (resource, action) = (resource.to_owned(), action.to_owned());
+        let params = parameters(params);
+        let travis = self.clone();
+        Box::new(self.home().and_then(move |home| {
+            home.expand(&resource, &action, &params)
+                .map(|(method, path)| {
+                    let uri = format!("{}{}", travis.host, path)
+                        .parse()
+                        .map_err(Error::from)
+                        .into_future();
+                    travis.request::<T, _>(method, body, uri)
+                })
+                .into_future()
+                .flatten()
+        }))
+    }
+
+    /// Loads the representation linked to by `href`, like the `href` of
+    /// a minimal resource embedded in another
+    ///
+    /// `href`s are relative to this client's host. Absolute urls on other
+    /// hosts are rejected, so credentials aren't sent elsewhere.
+    pub fn follow<T>(&self, href: &str) -> Future<T>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let url = if href.starts_with('/') {
+            Ok(format!("{}{}", self.host, href))
+        } else if href.starts_with(&format!("{}/", self.host)) {
+            Ok(href.to_owned())
+        } else {
+            Err(Error::from(
+                format!("{} is not a resource of {}", href, self.host),
+            ))
+        };
+        self.get(
+            url.and_then(|url| url.parse().map_err(Error::from))
+                .into_future(),
+        )
+    }
+
+    /// Resolves to the uri of `resource`'s `action`, with `query` appended
+    ///
+    /// Clients created `with_discovery` expand the action's template with
+    /// `params`, using `path` when the home document can't be loaded or
+    /// has no template `params` satisfy. Other clients use `path`.
+    pub(crate) fn locate(
+        &self,
+        resource: &'static str,
+        action: &'static str,
+        params: &[(&str, &str)],
+        path: String,
+        query: String,
+    ) -> Future<Uri> {
+        let host = self.host.clone();
+        let uri = move |path: String| -> Result<Uri> {
+            let url = if query.is_empty() {
+                format!("{}{}", host, path)
+            } else if path.contains('?') {
+                format!("{}{}&{}", host, path, query)
+            } else {
+                format!("{}{}?{}", host, path, query)
+            };
+            url.parse().map_err(Error::from)
+        };
+        if !self.discovery {
+            return Box::new(uri(path).into_future());
+        }
+        let params = parameters(params);
+        Box::new(self.home().then(move |home| {
+            match home.and_then(|home| home.expand(resource, action, &params)) {
+                Ok((_, discovered)) => uri(discovered),
+                Err(error) => {
+                    debug!(
+                        "using {} for {}.{}: {}",
+                        path,
+                        resource,
+                        action,
+                        error
+                    );
+                    uri(path)
+                }
+            }
+        }))
+    }
+
+    pub(crate) fn paginate<W>(
+        &self,
+        uri: Future<Uri>,
+        options: &PageOptions,
+    ) -> Stream<W::Item>
+    where
+        W: Paginated + 'static,
+    {
+        let (travis, options) = (self.clone(), options.clone());
+        Box::new(
+            uri.map(move |uri| {
+                pagination::stream::<C, W>(travis, &uri.to_string(), &options)
+            }).flatten_stream(),
+        )
+    }
+
+    pub(crate) fn patch<T, B, U>(
         &self,
-        uri: FutureResult<Uri, Error>,
+        uri: U,
         body: B,
     ) -> Future<T>
     where
         T: DeserializeOwned + 'static,
         B: Serialize,
+        U: IntoFuture<Item = Uri, Error = Error>,
+        U::Future: 'static,
     {
-        self.request::<T>(
+        self.request::<T, _>(
             Method::Patch,
             Some(serde_json::to_vec(&body).unwrap()),
             uri,
         )
     }
 
-    pub(crate) fn post<T, B>(
+    pub(crate) fn post<T, B, U>(
         &self,
-        uri: FutureResult<Uri, Error>,
+        uri: U,
         body: B,
     ) -> Future<T>
     where
         T: DeserializeOwned + 'static,
         B: Serialize,
+        U: IntoFuture<Item = Uri, Error = Error>,
+        U::Future: 'static,
     {
-        self.request::<T>(
+        self.request::<T, _>(
             Method::Post,
             Some(serde_json::to_vec(&body).unwrap()),
             uri,
         )
     }
 
-    pub(crate) fn get<T>(&self, uri: FutureResult<Uri, Error>) -> Future<T>
+    pub(crate) fn get<T, U>(&self, uri: U) -> Future<T>
     where
         T: DeserializeOwned + 'static,
+        U: IntoFuture<Item = Uri, Error = Error>,
+        U::Future: 'static,
     {
-        self.request::<T>(Method::Get, None, uri)
+        self.request::<T, _>(Method::Get, None, uri)
     }
 
-    pub(crate) fn delete(&self, uri: FutureResult<Uri, Error>) -> Future<()> {
-        Box::new(self.request::<()>(Method::Delete, None, uri).then(
-            |result| {
-                match result {
-                    Err(Error(ErrorKind::Codec(_), _)) => Ok(()),
-                    otherwise => otherwise,
-                }
-            },
+    /// Deletes a resource
+    ///
+    /// Success is determined by the response's status. Travis responds
+    /// with either an empty body or the deleted resource's representation,
+    /// neither of which needs decoding.
+    pub(crate) fn delete<U>(&self, uri: U) -> Future<()>
+    where
+        U: IntoFuture<Item = Uri, Error = Error>,
+        U::Future: 'static,
+    {
+        Box::new(self.execute(Method::Delete, None, uri).and_then(
+            |response| response.into_deleted(),
         ))
     }
 
-    pub(crate) fn request<T>(
+    pub(crate) fn request<T, U>(
         &self,
         method: Method,
         body: Option<Vec<u8>>,
-        uri: FutureResult<Uri, Error>,
+        uri: U,
     ) -> Future<T>
     where
         T: DeserializeOwned + 'static,
+        U: IntoFuture<Item = Uri, Error = Error>,
+        U::Future: 'static,
     {
-        let http_client = self.http.clone();
-        let credential = self.credential.clone();
-        let response = uri.and_then(move |uri| {
-            let mut req = Request::new(method, uri);
-            {
-                let mut headers = req.headers_mut();
-                headers.set(UserAgent::new(
-                    format!("Travis/{}", env!("CARGO_PKG_VERSION")),
-                ));
-                headers.set(TravisApiVersion("3".into()));
-                headers.set(ContentType::json());
-                if let Some(Credential::Token(ref token)) = credential {
-                    headers.set(Authorization(format!("token {}", token)))
-                }
-            }
-            for b in body {
-                req.set_body(b);
-            }
-
-            http_client.request(req).map_err(Error::from)
-        });
-        let result = response.and_then(|response| {
-            let status = response.status();
-            let body = response.body().concat2().map_err(Error::from);
-            body.and_then(move |body| if status.is_success() {
-                debug!("body {}", ::std::str::from_utf8(&body).unwrap());
-                serde_json::from_slice::<T>(&body).map_err(|error| {
-                    ErrorKind::Codec(error).into()
-                })
-            } else {
-                debug!(
-                    "{} err {}",
-                    status,
-                    ::std::str::from_utf8(&body).unwrap()
-                );
-                match serde_json::from_slice::<ClientError>(&body) {
-                    Ok(error) => Err(
-                        ErrorKind::Fault {
-                            code: status,
-                            error: error.error_message,
-                        }.into(),
-                    ),
-                    Err(error) => Err(ErrorKind::Codec(error).into()),
-                }
+        let result = self.execute(method, body, uri).and_then(|response| {
+            serde_json::from_slice::<T>(&response.body).map_err(|error| {
+                ErrorKind::Codec(error).into()
             })
         });
 
         Box::new(result)
     }
+
+    /// Sends a request, resolving to a successful response
+    ///
+    /// Requests refused for their credentials are replayed once after
+    /// exchanging the client's github token for a fresh travis token, if it
+    /// was constructed with one
+    pub(crate) fn execute<U>(
+        &self,
+        method: Method,
+        body: Option<Vec<u8>>,
+        uri: U,
+    ) -> Future<Response>
+    where
+        U: IntoFuture<Item = Uri, Error = Error>,
+        U::Future: 'static,
+    {
+        let client = self.clone();
+        Box::new(uri.into_future().and_then(move |uri| {
+            let stale = client.token();
+            client.attempt(method.clone(), uri.clone(), body.clone()).or_else(
+                move |error| -> Future<Response> {
+                    let github = client.auth.read().unwrap().github.clone();
+                    match github {
+                        Some(github) if refused(&error) => {
+                            warn!(
+                                "{} {} was refused, re-authenticating",
+                                method,
+                                uri
+                            );
+                            let replay = move |client: Self| {
+                                client.attempt(method, uri, body)
+                            };
+                            Box::new(
+                                client
+                                    .reauthenticate(stale, github)
+                                    .and_then(replay),
+                            )
+                        }
+                        _ => Box::new(future::err(error)),
+                    }
+                },
+            )
+        }))
+    }
+
+    /// the travis token currently sent with requests
+    fn token(&self) -> Option<String> {
+        self.auth.read().unwrap().token.clone()
+    }
+
+    /// exchanges `github` for a new travis token, unless another request
+    /// already replaced the `stale` token
+    fn reauthenticate(
+        &self,
+        stale: Option<String>,
+        github: String,
+    ) -> Future<Self> {
+        let client = self.clone();
+        if self.token() != stale {
+            return Box::new(future::ok(client));
+        }
+        Box::new(self.exchange(github).map(move |token| {
+            client.auth.write().unwrap().token = Some(token);
+            client
+        }))
+    }
+
+    /// Sends a request, retrying as permitted by this client's
+    /// `RetryPolicy`, resolving to a successful response
+    fn attempt(
+        &self,
+        method: Method,
+        uri: Uri,
+        body: Option<Vec<u8>>,
+    ) -> Future<Response> {
+        let client = self.clone();
+        retry::retry(
+            self.retry.clone(),
+            self.handle.clone(),
+            method.clone(),
+            uri.to_string(),
+            move || client.send(method.clone(), uri.clone(), body.clone()),
+        )
+    }
+
+    /// Sends a single request, resolving to its response regardless
+    /// of status
+    fn send(
+        &self,
+        method: Method,
+        uri: Uri,
+        body: Option<Vec<u8>>,
+    ) -> Future<Response> {
+        let mut headers = self.headers();
+        headers.set(TravisApiVersion("3".into()));
+        headers.set(ContentType::json());
+        if let Some(token) = self.token() {
+            headers.set(Authorization(format!("token {}", token)))
+        }
+        self.dispatch(transport::Request {
+            method,
+            url: uri.to_string(),
+            headers,
+            body,
+        })
+    }
+
+    /// the default headers and user agent sent with every request
+    fn headers(&self) -> Headers {
+        let mut headers = Headers::new();
+        headers.extend(self.headers.iter());
+        headers.set(UserAgent::new(self.user_agent.clone()));
+        headers
+    }
+
+    /// Hands `request` to this client's transport once its throttle
+    /// permits, failing it if it doesn't complete within this client's
+    /// timeout
+    fn dispatch(&self, request: transport::Request) -> Future<Response> {
+        logging::request(
+            &request.method,
+            &request.url,
+            &request.headers,
+            request.body.as_ref().map(Vec::as_slice),
+            self.log_secrets,
+        );
+        let transport = self.transport.clone();
+        let handle = self.handle.clone();
+        let rate_limit = self.rate_limit.clone();
+        let timeout = self.timeout;
+        let secrets = self.log_secrets;
+        let send = move |_| {
+            let method = request.method.clone();
+            let url = request.url.clone();
+            let exchange = transport.send(request).map(move |response| {
+                logging::response(
+                    &method,
+                    &url,
+                    response.status,
+                    &response.body,
+                    secrets,
+                );
+                let response = Response {
+                    method,
+                    url,
+                    status: response.status,
+                    headers: response.headers,
+                    body: response.body,
+                };
+                if let Some(limit) = RateLimit::from_response(&response) {
+                    *rate_limit.lock().unwrap() = Some(limit);
+                }
+                response
+            });
+            within(timeout, &handle, Box::new(exchange))
+        };
+        let (throttle, delays) = (self.throttle.clone(), self.handle.clone());
+        let throttled = move |slot: Option<Slot>| -> Future<Response> {
+            let sent = match throttle.as_ref().and_then(Throttle::reserve) {
+                Some(wait) => {
+                    debug!("throttling request for {:?}", wait);
+                    Box::new(
+                        Timeout::new(wait, &delays)
+                            .into_future()
+                            .flatten()
+                            .map_err(Error::from)
+                            .and_then(send),
+                    ) as Future<Response>
+                }
+                None => send(()),
+            };
+            // the slot is held until the response, or error, arrives
+            Box::new(sent.then(move |result| {
+                drop(slot);
+                result
+            }))
+        };
+        match self.slots {
+            Some(ref slots) => {
+                Box::new(slots.claim().map(Some).and_then(throttled))
+            }
+            None => throttled(None),
+        }
+    }
+}
+
+impl<C> fmt::Debug for Client<C>
+where
+    C: Clone + Connect,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("host", &self.host)
+            .field("retry", &self.retry)
+            .field("throttle", &self.throttle)
+            .field("timeout", &self.timeout)
+            .field("user_agent", &self.user_agent)
+            .finish()
+    }
+}
+
+impl Client<NoConnector> {
+    /// Creates a Travis client sending requests with `transport`, resolving
+    /// once `Credential::Github` credentials are exchanged for a travis
+    /// token
+    ///
+    /// `handle` drives timeouts, retries and throttling
+    pub fn from_transport<H, T>(
+        host: H,
+        transport: T,
+        handle: &Handle,
+        credential: Option<Credential>,
+    ) -> Future<Self>
+    where
+        H: Into<String>,
+        T: Transport + 'static,
+    {
+        Client::with_transport(host, Rc::new(transport), handle.clone())
+            .authenticate(credential)
+    }
+}
+
+/// fails `future` with `hyper::Error::Timeout` unless it resolves within
+/// `timeout`
+fn within<T>(
+    timeout: Option<Duration>,
+    handle: &Handle,
+    future: Future<T>,
+) -> Future<T>
+where
+    T: 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return future,
+    };
+    let expired = Timeout::new(timeout, handle)
+        .into_future()
+        .flatten()
+        .map_err(Error::from)
+        .and_then(|_| Err(ErrorKind::Http(hyper::Error::Timeout).into()));
+    Box::new(future.select(expired).map(|(t, _)| t).map_err(|(e, _)| e))
+}
+
+/// true for errors travis responds with when it doesn't accept a token
+///
+/// `insufficient_access` errors are excluded: the token was accepted but
+/// lacks permission, which a new token won't change
+fn refused(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::Api(ApiError::LoginRequired(_)) |
+        ErrorKind::Api(ApiError::WrongCredentials(_)) => true,
+        ErrorKind::Api(ref error) => {
+            StatusCode::Unauthorized == error.fault().status
+        }
+        _ => false,
+    }
+}
+
+/// A response received from travis, before decoding
+pub(crate) struct Response {
+    pub method: Method,
+    pub url: String,
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// interprets a successful response to a deletion
+    fn into_deleted(self) -> Result<()> {
+        if StatusCode::NoContent == self.status ||
+            self.body.iter().all(u8::is_ascii_whitespace)
+        {
+            return Ok(());
+        }
+        match serde_json::from_slice::<ClientError>(&self.body) {
+            Ok(ClientError { kind: Some(ref kind), .. }) if kind == "error" => {
+                Err(self.into_error())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// converts an unsuccessful response into an error
+    pub(crate) fn into_error(self) -> Error {
+        let limit = RateLimit::from_response(&self);
+        let limited = StatusCode::TooManyRequests == self.status ||
+            StatusCode::Forbidden == self.status &&
+                limit.as_ref().map(RateLimit::exhausted).unwrap_or(false);
+        if limited {
+            return ErrorKind::RateLimited {
+                retry_after: retry::retry_after(&self).or_else(|| {
+                    limit.and_then(|limit| limit.reset_in())
+                }),
+            }.into();
+        }
+        api_error(self.status, self.method, self.url, &self.body)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use futures::Stream as StdStream;
+    use env::EnvVarCreate;
+    use testing::{Canned, client, serve, transported};
+    use jobs::{Job, MinimalJob};
+    use transport::Memory;
+    use transport::cassette::Replay;
+
+    /// a client replaying the interactions recorded in a fixture cassette
+    fn replay(
+        fixture: &str,
+        core: &mut Core,
+    ) -> (Client<NoConnector>, Replay) {
+        let replay = Replay::load(format!(
+            "{}/tests/fixtures/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            fixture
+        )).unwrap();
+        let travis = transported(replay.clone(), core);
+        (travis, replay)
+    }
+
+    #[test]
+    fn builds_iterate_across_pages() {
+        let mut core = Core::new().unwrap();
+        let (travis, replay) = replay("builds", &mut core);
+        let builds = core.run(
+            travis
+                .builds("softprops/travis")
+                .iter(&Default::default())
+                .collect(),
+        ).unwrap();
+        assert_eq!(
+            builds.iter().map(|build| build.id).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert!(replay.remaining().is_empty());
+    }
+
+    #[test]
+    fn repos_iterate_across_pages() {
+        let mut core = Core::new().unwrap();
+        let (travis, replay) = replay("repos", &mut core);
+        let repos = core.run(
+            travis.repos().iter("softprops", &Default::default()).collect(),
+        ).unwrap();
+        assert_eq!(
+            repos.iter().map(|repo| repo.slug.as_str()).collect::<Vec<_>>(),
+            vec!["softprops/travis", "softprops/hubcaps", "softprops/envy"]
+        );
+        assert!(replay.remaining().is_empty());
+    }
+
+    #[test]
+    fn env_vars_are_managed() {
+        let mut core = Core::new().unwrap();
+        let (travis, replay) = replay("env", &mut core);
+        let env = travis.env("softprops/travis");
+        let vars = core.run(env.vars()).unwrap();
+        assert_eq!(
+            vars.iter().map(|var| var.id.as_str()).collect::<Vec<_>>(),
+            vec!["a1", "b2"]
+        );
+        let var = core.run(env.get("a1")).unwrap();
+        assert_eq!(var.name, Some("RUST_LOG".into()));
+        let created = core.run(env.set(EnvVarCreate {
+            name: "DEPLOY_TOKEN".into(),
+            value: "s3cret".into(),
+            public: false,
+        })).unwrap();
+        assert_eq!(created.id, "c3");
+        core.run(env.delete("c3")).unwrap();
+        assert!(replay.remaining().is_empty());
+    }
+
+    #[test]
+    fn unrecorded_requests_fail() {
+        let mut core = Core::new().unwrap();
+        let (travis, _) = replay("env", &mut core);
+        match core.run(travis.env("softprops/travis").delete("a1")) {
+            Err(Error(ErrorKind::Unmatched(ref method, ref url), _)) => {
+                assert_eq!(method, "DELETE");
+                assert!(url.ends_with("/repo/softprops%2Ftravis/env_var/a1"));
+            }
+            otherwise => panic!("unexpected {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn hrefs_are_followed() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(
+            Method::Get,
+            "/job/5",
+            200,
+            r#"{"@type": "job", "@href": "/job/5", "id": 5, "number": "7.1",
+            "state": "passed", "started_at": null, "finished_at": null,
+            "queue": "builds.gce", "owner": {"id": 1, "login": "softprops"}}"#,
+        );
+        let travis = transported(memory.clone(), &mut core);
+        let embedded: MinimalJob = serde_json::from_str(
+            r#"{"@type": "job", "@href": "/job/5", "id": 5}"#,
+        ).unwrap();
+        let href = embedded.href.unwrap();
+        let job = core.run(travis.follow::<Job>(&href)).unwrap();
+        assert_eq!(job.number, Some("7.1".into()));
+        let absolute = format!("https://api.travis-ci.org{}", href);
+        assert_eq!(core.run(travis.follow::<Job>(&absolute)).unwrap().id, 5);
+        assert!(
+            core.run(travis.follow::<Job>("https://example.com/job/5"))
+                .is_err()
+        );
+        assert_eq!(memory.requests().len(), 2);
+    }
+
+    #[test]
+    fn minimal_resources_are_fetched() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(
+            Method::Get,
+            "/job/5",
+            200,
+            r#"{"@type": "job", "@href": "/job/5",
+            "@representation": "standard", "id": 5, "number": "7.1",
+            "state": "passed", "started_at": null, "finished_at": null,
+            "queue": "builds.gce", "owner": {"id": 1, "login": "softprops"}}"#,
+        );
+        let travis = transported(memory.clone(), &mut core);
+        let jobs: Vec<Embedded<MinimalJob, Job>> = serde_json::from_str(
+            r#"[{"@type": "job", "@representation": "minimal", "id": 5},
+            {"@type": "job", "@representation": "standard", "id": 6,
+            "number": "7.2", "state": "failed", "started_at": null,
+            "finished_at": null, "queue": "builds.gce",
+            "owner": {"id": 1, "login": "softprops"}}]"#,
+        ).unwrap();
+        assert!(jobs[0].standard().is_none());
+        let numbers = jobs.into_iter()
+            .map(|job| core.run(travis.standard(job)).unwrap().number)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, vec![Some("7.1".into()), Some("7.2".into())]);
+        assert_eq!(memory.requests().len(), 1);
+    }
+
+    #[test]
+    fn unlabeled_representations_are_inferred() {
+        let jobs: Vec<Embedded<MinimalJob, Job>> = serde_json::from_str(
+            r#"[{"@type": "job", "id": 5},
+            {"@type": "job", "id": 6, "number": "7.2", "state": "failed",
+            "started_at": null, "finished_at": null, "queue": "builds.gce",
+            "owner": {"id": 1, "login": "softprops"}}]"#,
+        ).unwrap();
+        match jobs[0] {
+            Embedded::Minimal(ref job) => assert_eq!(job.id, 5),
+            ref otherwise => panic!("unexpected {:?}", otherwise),
+        }
+        assert_eq!(jobs[1].standard().map(|job| job.id), Some(6));
+        let malformed = serde_json::from_str::<Embedded<MinimalJob, Job>>(
+            r#"{"@type": "job", "id": 7, "number": 7}"#,
+        );
+        assert!(malformed.is_err());
+    }
+
+    #[test]
+    fn actions_are_discovered() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(
+            Method::Get,
+            "/",
+            200,
+            r#"{"@type": "home", "resources": {"env_vars": {"actions": {
+              "for_repository": [{"request_method": "GET",
+                "uri_template": "/repo/{repository.slug}/env_vars"}]
+            }}}}"#,
+        );
+        memory.respond(
+            Method::Get,
+            "/repo/foo%2Fbar/env_vars",
+            200,
+            r#"{"env_vars": []}"#,
+        );
+        let travis = transported(memory.clone(), &mut core);
+        for _ in 0..2 {
+            let vars = core.run(travis.action::<serde_json::Value>(
+                "env_vars",
+                "for_repository",
+                &[("repository.slug", "foo/bar")],
+            )).unwrap();
+            assert_eq!(vars["env_vars"], json!([]));
+        }
+        match core.run(travis.action::<serde_json::Value>(
+            "env_vars",
+            "create",
+            &[("repository.slug", "foo/bar")],
+        )) {
+            Err(Error(ErrorKind::UnsupportedAction(_, ref action), _)) => {
+                assert_eq!(action, "create")
+            }
+            otherwise => panic!("unexpected {:?}", otherwise),
+        }
+        let paths = memory
+            .requests()
+            .iter()
+            .map(|request| request.path().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["/", "/repo/foo%2Fbar/env_vars", "/repo/foo%2Fbar/env_vars"]
+        );
+    }
+
+    #[test]
+    fn typed_resources_follow_discovered_paths() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(
+            Method::Get,
+            "/",
+            200,
+            r#"{"@type": "home", "resources": {"env_vars": {"actions": {
+              "for_repository": [{"request_method": "GET",
+                "uri_template": "/v3/repo/{repository.slug}/vars"}]
+            }}}}"#,
+        );
+        memory.respond(
+            Method::Get,
+            "/v3/repo/foo%2Fbar/vars",
+            200,
+            r#"{"env_vars": []}"#,
+        );
+        memory.respond(
+            Method::Get,
+            "/repo/foo%2Fbar/env_var/1",
+            200,
+            r#"{"@type": "env_var", "id": "1", "name": "FOO",
+            "@permissions": {"read": true, "write": true}}"#,
+        );
+        let travis = transported(memory.clone(), &mut core).with_discovery();
+        assert!(core.run(travis.env("foo/bar").vars()).unwrap().is_empty());
+        assert_eq!(core.run(travis.env("foo/bar").get("1")).unwrap().id, "1");
+        let paths = memory
+            .requests()
+            .iter()
+            .map(|request| request.path().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec!["/", "/v3/repo/foo%2Fbar/vars", "/repo/foo%2Fbar/env_var/1"]
+        );
+    }
+
+    #[test]
+    fn typed_resources_fall_back_without_a_home_document() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(Method::Get, "/build/1/jobs", 200, r#"{"jobs": []}"#);
+        let travis = transported(memory.clone(), &mut core).with_discovery();
+        assert!(core.run(travis.jobs(1).list()).unwrap().is_empty());
+        let paths = memory
+            .requests()
+            .iter()
+            .map(|request| request.path().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/", "/build/1/jobs"]);
+    }
+
+    fn delete(response: Canned) -> Result<()> {
+        let (host, requests) = serve(vec![response]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core);
+        let result = core.run(travis.env("foo/bar").delete("1"));
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["DELETE /repo/foo%2Fbar/env_var/1"]
+        );
+        result
+    }
+
+    #[test]
+    fn delete_accepts_empty_responses() {
+        assert!(delete(Canned::new(204, "")).is_ok())
+    }
+
+    #[test]
+    fn delete_accepts_deleted_representations() {
+        assert!(
+            delete(Canned::new(
+                200,
+                r#"{"@type": "env_var", "id": "1", "name": "FOO"}"#,
+            )).is_ok()
+        )
+    }
+
     #[test]
-    fn it_works() {}
+    fn delete_rejects_unstructured_server_errors() {
+        match delete(Canned::new(503, "<html>unavailable</html>")) {
+            Err(Error(ErrorKind::Api(ApiError::Other(ref fault)), _)) => {
+                assert_eq!(fault.status, StatusCode::ServiceUnavailable)
+            }
+            otherwise => panic!("unexpected {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn delete_rejects_error_responses() {
+        match delete(Canned::new(
+            404,
+            r#"{
+              "@type": "error",
+              "error_type": "not_found",
+              "error_message": "env_var not found (or insufficient access)",
+              "resource_type": "env_var"
+            }"#,
+        )) {
+            Err(Error(ErrorKind::Api(ApiError::NotFound(_)), _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn clients_are_constructed_within_futures() {
+        let (host, requests) = serve(vec![
+            Canned::new(200, r#"{"access_token": "travis-token"}"#),
+            Canned::new(200, r#"{"env_vars": []}"#),
+        ]);
+        let mut core = Core::new().unwrap();
+        let http = HyperClient::configure().keep_alive(false).build(
+            &core.handle(),
+        );
+        let vars = Client::custom_async(
+            host,
+            http,
+            Some(Credential::Github("github-token".into())),
+            &core.handle(),
+        ).and_then(|travis| travis.env("foo/bar").vars());
+        assert!(core.run(vars).unwrap().is_empty());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["POST /auth/github", "GET /repo/foo%2Fbar/env_vars"]
+        );
+    }
+
+    const REFUSED: &str = r#"{
+      "error_type": "login_required",
+      "error_message": "login required"
+    }"#;
+
+    const DENIED: &str = r#"{
+      "error_type": "insufficient_access",
+      "error_message": "operation requires read access"
+    }"#;
+
+    #[test]
+    fn refused_tokens_are_exchanged_again() {
+        let (host, requests) = serve(vec![
+            Canned::new(200, r#"{"access_token": "revoked"}"#),
+            Canned::new(403, REFUSED),
+            Canned::new(200, r#"{"access_token": "fresh"}"#),
+            Canned::new(200, r#"{"env_vars": []}"#),
+        ]);
+        let mut core = Core::new().unwrap();
+        let http = HyperClient::configure().keep_alive(false).build(
+            &core.handle(),
+        );
+        let travis = Client::custom(
+            host,
+            http,
+            Some(Credential::Github("github-token".into())),
+            &mut core,
+        ).unwrap();
+        let clone = travis.clone();
+        assert!(core.run(travis.env("foo/bar").vars()).unwrap().is_empty());
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                "POST /auth/github",
+                "GET /repo/foo%2Fbar/env_vars",
+                "POST /auth/github",
+                "GET /repo/foo%2Fbar/env_vars",
+            ]
+        );
+        assert_eq!(clone.token(), Some("fresh".into()));
+    }
+
+    #[test]
+    fn insufficient_access_is_not_exchanged_again() {
+        let (host, requests) = serve(vec![
+            Canned::new(200, r#"{"access_token": "travis-token"}"#),
+            Canned::new(403, DENIED),
+        ]);
+        let mut core = Core::new().unwrap();
+        let http = HyperClient::configure().keep_alive(false).build(
+            &core.handle(),
+        );
+        let travis = Client::custom(
+            host,
+            http,
+            Some(Credential::Github("github-token".into())),
+            &mut core,
+        ).unwrap();
+        match core.run(travis.env("foo/bar").vars()) {
+            Err(Error(ErrorKind::Api(ApiError::InsufficientAccess(_)), _)) => {
+                ()
+            }
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["POST /auth/github", "GET /repo/foo%2Fbar/env_vars"]
+        );
+    }
+
+    #[test]
+    fn refused_travis_tokens_fail() {
+        let (host, requests) = serve(vec![Canned::new(403, REFUSED)]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core);
+        match core.run(travis.env("foo/bar").vars()) {
+            Err(Error(ErrorKind::Api(ApiError::LoginRequired(_)), _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unknown_states_deserialize() {
+        assert_eq!(
+            serde_json::from_str::<Vec<State>>(r#"["passed", "queued"]"#)
+                .unwrap(),
+            vec![State::Passed, State::Unknown("queued".into())]
+        );
+        assert_eq!(State::Unknown("booting".into()).to_string(), "booting")
+    }
 }
@@ -0,0 +1,182 @@
+//! Logging of requests and responses with secrets redacted
+//!
+//! Requests are logged at `debug` level. Unless a client is configured
+//! with `dangerously_log_secrets`, `Authorization` headers, travis and
+//! github tokens, and env var values are replaced with `[redacted]`.
+
+use hyper::{Method, StatusCode};
+use hyper::header::Headers;
+use serde_json::{self, Value};
+
+pub(crate) const REDACTED: &str = "[redacted]";
+
+/// headers whose values are credentials
+const SECRET_HEADERS: &[&str] = &["authorization", "proxy-authorization"];
+
+/// fields whose values are secrets wherever they appear
+const SECRET_FIELDS: &[&str] =
+    &["access_token", "github_token", "env_var.value"];
+
+/// logs a request about to be sent
+pub(crate) fn request(
+    method: &Method,
+    url: &str,
+    headers: &Headers,
+    body: Option<&[u8]>,
+    secrets: bool,
+) {
+    debug!(
+        "request {} {} headers [{}] body {}",
+        method,
+        url,
+        redact_headers(headers, secrets),
+        body.map(|body| redact_body(body, secrets)).unwrap_or_default()
+    );
+}
+
+/// logs a response received for a request
+pub(crate) fn response(
+    method: &Method,
+    url: &str,
+    status: StatusCode,
+    body: &[u8],
+    secrets: bool,
+) {
+    debug!(
+        "response {} {} status {} body {}",
+        method,
+        url,
+        status,
+        redact_body(body, secrets)
+    );
+}
+
+/// true for headers whose values are credentials
+pub(crate) fn secret_header(name: &str) -> bool {
+    SECRET_HEADERS.iter().any(|secret| name.eq_ignore_ascii_case(secret))
+}
+
+pub(crate) fn redact_headers(headers: &Headers, secrets: bool) -> String {
+    headers
+        .iter()
+        .map(|header| {
+            if secret_header(header.name()) && !secrets {
+                format!("{}: {}", header.name(), REDACTED)
+            } else {
+                format!("{}: {}", header.name(), header.value_string())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(crate) fn redact_body(body: &[u8], secrets: bool) -> String {
+    if secrets {
+        return String::from_utf8_lossy(body).into_owned();
+    }
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value, false);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+/// replaces secrets within `value`. `env_var` is true within env var
+/// representations, whose `value`s may be private
+fn redact(value: &mut Value, env_var: bool) {
+    match *value {
+        Value::Object(ref mut fields) => {
+            let env_var = env_var ||
+                fields.get("@type").and_then(Value::as_str) == Some("env_var");
+            for (name, value) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&name.as_str()) ||
+                    env_var && name == "value"
+                {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    redact(value, env_var || name == "env_vars")
+                }
+            }
+        }
+        Value::Array(ref mut values) => {
+            for value in values {
+                redact(value, env_var)
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::{Authorization, ContentType};
+
+    #[test]
+    fn tokens_are_redacted() {
+        assert_eq!(
+            redact_body(br#"{"access_token": "s3cret"}"#, false),
+            r#"{"access_token":"[redacted]"}"#
+        );
+        assert_eq!(
+            redact_body(br#"{"github_token": "s3cret"}"#, false),
+            r#"{"github_token":"[redacted]"}"#
+        );
+    }
+
+    #[test]
+    fn env_var_values_are_redacted() {
+        let listed = redact_body(
+            br#"{"env_vars": [{"name": "KEY", "value": "s3cret"}]}"#,
+            false,
+        );
+        assert!(!listed.contains("s3cret"));
+        assert!(listed.contains(r#""name":"KEY""#));
+        let single = redact_body(
+            br#"{"@type": "env_var", "name": "KEY", "value": "s3cret"}"#,
+            false,
+        );
+        assert!(!single.contains("s3cret"));
+        let created = redact_body(
+            br#"{"env_var.name": "KEY", "env_var.value": "s3cret"}"#,
+            false,
+        );
+        assert!(!created.contains("s3cret"));
+    }
+
+    #[test]
+    fn other_values_are_kept() {
+        assert_eq!(
+            redact_body(br#"{"value": "kept"}"#, false),
+            r#"{"value":"kept"}"#
+        );
+        assert_eq!(
+            redact_body(b"<html>oops</html>", false),
+            "<html>oops</html>"
+        );
+    }
+
+    #[test]
+    fn authorization_headers_are_redacted() {
+        let mut headers = Headers::new();
+        headers.set(Authorization("token s3cret".to_owned()));
+        headers.set(ContentType::json());
+        let redacted = redact_headers(&headers, false);
+        assert!(!redacted.contains("s3cret"));
+        assert!(redacted.contains("Authorization: [redacted]"));
+        assert!(redacted.contains("Content-Type: application/json"));
+    }
+
+    #[test]
+    fn secrets_may_be_logged_explicitly() {
+        let mut headers = Headers::new();
+        headers.set(Authorization("token s3cret".to_owned()));
+        assert!(redact_headers(&headers, true).contains("s3cret"));
+        assert!(
+            redact_body(br#"{"access_token": "s3cret"}"#, true)
+                .contains("s3cret")
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Generic pagination over travis collection resources
+
+use {Client, Error, Future, Stream};
+use futures::{Async, Future as StdFuture, IntoFuture, Poll,
+              Stream as StdStream};
+use futures::stream;
+use hyper::client::Connect;
+use serde::de::DeserializeOwned;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use url::Url;
+
+/// Pagination metadata travis includes with collection responses
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct Pagination {
+    #[serde(default)]
+    pub limit: usize,
+    #[serde(default)]
+    pub offset: usize,
+    pub count: usize,
+    pub next: Option<Page>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct Page {
+    #[serde(rename = "@href")]
+    pub href: String,
+}
+
+/// A collection response which carries `@pagination` metadata
+///
+/// Implementing this for a resource's wrapper type is all that's needed
+/// to stream its items across pages with `Client::paginate`
+pub(crate) trait Paginated: DeserializeOwned + 'static {
+    /// The type of item collected in each page
+    type Item: 'static;
+
+    /// pagination metadata for this page
+    fn pagination(&self) -> &Pagination;
+
+    /// consumes this page, yielding its items
+    fn into_items(self) -> Vec<Self::Item>;
+}
+
+/// Options controlling how pages are requested when iterating
+/// over a collection
+#[derive(Builder, Debug, Clone, Default)]
+#[builder(setter(into), default)]
+pub struct PageOptions {
+    /// maximum number of items to yield across all pages
+    max_items: Option<usize>,
+    /// number of items to skip before the first item yielded
+    offset: Option<usize>,
+    /// number of items to request per page
+    page_size: Option<usize>,
+    /// request the next page while the current one is being consumed
+    prefetch: bool,
+    /// request up to this many pages at once
+    ///
+    /// Page offsets are computed up front from the `count` reported with
+    /// the first page, so items are only consistent for collections which
+    /// don't change during the scan. Items are still yielded in order, and
+    /// streams stop after a page comes back short.
+    concurrency: Option<usize>,
+}
+
+impl PageOptions {
+    /// Creates a builder for options requesting one page at a time
+    pub fn builder() -> PageOptionsBuilder {
+        PageOptionsBuilder::default()
+    }
+
+    /// applies offset and page size hints to the query of a first page url
+    fn first_page(&self, url: &str) -> Result<String, Error> {
+        let limit = match (self.page_size, self.max_items) {
+            (Some(size), Some(max)) => Some(size.min(max)),
+            (size, _) => size,
+        };
+        with_query(url, limit, self.offset)
+    }
+}
+
+/// replaces the `limit` and `offset` query parameters of a url
+fn with_query(
+    url: &str,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<String, Error> {
+    if limit.is_none() && offset.is_none() {
+        return Ok(url.to_owned());
+    }
+    let mut url = Url::parse(url)
+        .map_err(|e| Error::from(format!("invalid url {}: {}", url, e)))?;
+    let pairs = url.query_pairs()
+        .into_owned()
+        .filter(|&(ref k, _)| {
+            !(k == "limit" && limit.is_some() ||
+                  k == "offset" && offset.is_some())
+        })
+        .collect::<Vec<_>>();
+    {
+        let mut query = url.query_pairs_mut();
+        query.clear().extend_pairs(pairs);
+        if let Some(limit) = limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+        if let Some(offset) = offset {
+            query.append_pair("offset", &offset.to_string());
+        }
+    }
+    Ok(url.into_string())
+}
+
+/// the number of items per page, as reported or as the first page had
+fn page_limit(pagination: &Pagination, page_len: usize) -> usize {
+    if pagination.limit > 0 {
+        pagination.limit
+    } else {
+        page_len
+    }
+}
+
+/// offsets of the pages following a first page, bounded by `max_items`
+fn offsets(
+    pagination: &Pagination,
+    page_len: usize,
+    max_items: Option<usize>,
+) -> Vec<usize> {
+    let limit = page_limit(pagination, page_len);
+    if limit == 0 {
+        return Vec::new();
+    }
+    let end = max_items
+        .map(|max| (pagination.offset + max).min(pagination.count))
+        .unwrap_or(pagination.count);
+    (1..)
+        .map(|page| pagination.offset + page * limit)
+        .take_while(|offset| *offset < end)
+        .collect()
+}
+
+/// Streams items across all pages of a collection starting at `url`
+pub(crate) fn stream<C, W>(
+    travis: Client<C>,
+    url: &str,
+    options: &PageOptions,
+) -> Stream<W::Item>
+where
+    C: Clone + Connect,
+    W: Paginated,
+{
+    match options.concurrency {
+        Some(concurrency) if concurrency > 1 => {
+            concurrent::<C, W>(travis, url, options, concurrency)
+        }
+        _ => Box::new(Pages::<C, W>::new(travis, url, options)),
+    }
+}
+
+/// Streams items from pages requested `concurrency` at a time, in order
+fn concurrent<C, W>(
+    travis: Client<C>,
+    url: &str,
+    options: &PageOptions,
+    concurrency: usize,
+) -> Stream<W::Item>
+where
+    C: Clone + Connect,
+    W: Paginated,
+{
+    let first = match options.first_page(url) {
+        Ok(first) => first,
+        Err(err) => return Box::new(stream::once(Err(err))),
+    };
+    let max_items = options.max_items;
+    let items = travis
+        .get::<W, _>(first.parse().map_err(Error::from).into_future())
+        .map(move |page| {
+            let pagination = page.pagination().clone();
+            let items = page.into_items();
+            let limit = page_limit(&pagination, items.len());
+            let urls = offsets(&pagination, items.len(), max_items)
+                .into_iter()
+                .map(|offset| with_query(&first, None, Some(offset)))
+                .collect::<Vec<_>>();
+            let short = Rc::new(Cell::new(false));
+            let rest = stream::iter_ok::<_, Error>(urls)
+                .map(move |url| {
+                    travis.get::<W, _>(
+                        url.and_then(|url| url.parse().map_err(Error::from))
+                            .into_future(),
+                    )
+                })
+                .buffered(concurrency.max(1))
+                .map(W::into_items)
+                // a short page means the collection shrank since the first
+                // page, so later offsets no longer line up
+                .take_while(move |items| {
+                    let more = !short.get();
+                    short.set(short.get() || items.len() < limit);
+                    Ok(more)
+                })
+                .map(stream::iter_ok)
+                .flatten();
+            stream::iter_ok(items).chain(rest)
+        })
+        .into_stream()
+        .flatten();
+    match max_items {
+        Some(max) => Box::new(items.take(max as u64)),
+        None => Box::new(items),
+    }
+}
+
+/// A stream of items fetched page by page by following
+/// `@pagination.next` links
+struct Pages<C, W>
+where
+    C: Clone + Connect,
+    W: Paginated,
+{
+    travis: Client<C>,
+    items: VecDeque<W::Item>,
+    next: Option<String>,
+    pending: Option<Future<W>>,
+    ready: Option<W>,
+    remaining: Option<usize>,
+    prefetch: bool,
+}
+
+impl<C, W> Pages<C, W>
+where
+    C: Clone + Connect,
+    W: Paginated,
+{
+    fn new(
+        travis: Client<C>,
+        url: &str,
+        options: &PageOptions,
+    ) -> Self {
+        let pending = match options.first_page(url) {
+            Ok(first) => travis.get::<W, _>(
+                first.parse().map_err(Error::from).into_future(),
+            ),
+            Err(err) => Box::new(Err(err).into_future()),
+        };
+        Pages {
+            travis,
+            items: VecDeque::new(),
+            next: None,
+            pending: Some(pending),
+            ready: None,
+            remaining: options.max_items,
+            prefetch: options.prefetch,
+        }
+    }
+
+    fn load(&mut self, page: W) {
+        self.next = page.pagination().next.as_ref().map(
+            |page| page.href.clone(),
+        );
+        self.items.extend(page.into_items());
+    }
+
+    /// true when items already buffered will satisfy `max_items`
+    fn satisfied(&self) -> bool {
+        self.remaining.map(|r| r <= self.items.len()).unwrap_or(false)
+    }
+}
+
+impl<C, W> StdStream for Pages<C, W>
+where
+    C: Clone + Connect,
+    W: Paginated,
+{
+    type Item = W::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.remaining == Some(0) {
+                return Ok(Async::Ready(None));
+            }
+            if self.items.is_empty() {
+                if let Some(page) = self.ready.take() {
+                    self.load(page);
+                    continue;
+                }
+            }
+            if self.pending.is_none() && self.ready.is_none() &&
+                (self.items.is_empty() || self.prefetch) &&
+                !self.satisfied()
+            {
+                if let Some(href) = self.next.take() {
+                    let uri = format!(
+                        "{host}{path}",
+                        host = self.travis.host,
+                        path = href
+                    ).parse()
+                        .map_err(Error::from)
+                        .into_future();
+                    self.pending = Some(self.travis.get::<W, _>(uri));
+                }
+            }
+            let polled = match self.pending.as_mut() {
+                Some(pending) => Some(pending.poll()?),
+                None => None,
+            };
+            match polled {
+                Some(Async::Ready(page)) => {
+                    self.pending = None;
+                    if self.items.is_empty() {
+                        self.load(page);
+                    } else {
+                        self.ready = Some(page);
+                    }
+                    continue;
+                }
+                Some(Async::NotReady) if self.items.is_empty() => {
+                    return Ok(Async::NotReady)
+                }
+                _ => (),
+            }
+            return match self.items.pop_front() {
+                Some(item) => {
+                    self.remaining = self.remaining.map(|r| r - 1);
+                    Ok(Async::Ready(Some(item)))
+                }
+                None => Ok(Async::Ready(None)),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::Method;
+    use testing::transported;
+    use tokio_core::reactor::Core;
+    use transport::Memory;
+
+    #[derive(Deserialize)]
+    struct Numbers {
+        numbers: Vec<usize>,
+        #[serde(rename = "@pagination")]
+        pagination: Pagination,
+    }
+
+    impl Paginated for Numbers {
+        type Item = usize;
+
+        fn pagination(&self) -> &Pagination {
+            &self.pagination
+        }
+
+        fn into_items(self) -> Vec<usize> {
+            self.numbers
+        }
+    }
+
+    /// a page of `numbers` out of `count`, linking to the page at `next`
+    fn page(numbers: &[usize], count: usize, next: Option<&str>) -> String {
+        json!({
+            "numbers": numbers,
+            "@pagination": {
+                "limit": 2,
+                "count": count,
+                "next": next.map(|href| json!({ "@href": href })),
+            },
+        }).to_string()
+    }
+
+    fn pagination(limit: usize, offset: usize, count: usize) -> Pagination {
+        Pagination {
+            limit,
+            offset,
+            count,
+            next: None,
+        }
+    }
+
+    #[test]
+    fn pages_stop_at_empty_pages() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        // routes with queries first, as `/numbers` matches any query
+        memory.respond(
+            Method::Get,
+            "/numbers?offset=2",
+            200,
+            page(&[3], 3, Some("/numbers?offset=3")),
+        );
+        memory.respond(
+            Method::Get,
+            "/numbers?offset=3",
+            200,
+            page(&[], 3, None),
+        );
+        memory.respond(
+            Method::Get,
+            "/numbers",
+            200,
+            page(&[1, 2], 3, Some("/numbers?offset=2")),
+        );
+        let travis = transported(memory.clone(), &mut core);
+        let numbers = core.run(
+            stream::<_, Numbers>(
+                travis,
+                "https://api.travis-ci.org/numbers",
+                &PageOptions::default(),
+            ).collect(),
+        ).unwrap();
+        assert_eq!(numbers, vec![1, 2, 3]);
+        assert_eq!(memory.requests().len(), 3);
+    }
+
+    #[test]
+    fn concurrent_pages_stop_at_short_pages() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        // the collection shrank after the first page was counted
+        memory.respond(
+            Method::Get,
+            "/numbers?offset=2",
+            200,
+            page(&[3], 6, None),
+        );
+        memory.respond(
+            Method::Get,
+            "/numbers?offset=4",
+            200,
+            page(&[5, 6], 6, None),
+        );
+        memory.respond(Method::Get, "/numbers", 200, page(&[1, 2], 6, None));
+        let travis = transported(memory.clone(), &mut core);
+        let numbers = core.run(
+            stream::<_, Numbers>(
+                travis,
+                "https://api.travis-ci.org/numbers",
+                &PageOptions::builder().concurrency(2).build().unwrap(),
+            ).collect(),
+        ).unwrap();
+        assert_eq!(numbers, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn offsets_cover_remaining_pages() {
+        assert_eq!(
+            offsets(&pagination(25, 0, 80), 25, None),
+            vec![25, 50, 75]
+        );
+        assert!(offsets(&pagination(25, 10, 35), 25, None).is_empty());
+        assert_eq!(offsets(&pagination(25, 0, 80), 25, Some(50)), vec![25]);
+        assert_eq!(offsets(&pagination(0, 0, 30), 10, None), vec![10, 20]);
+    }
+
+    #[test]
+    fn first_page_without_hints_is_untouched() {
+        let url = "https://api.travis-ci.org/repo/foo%2Fbar/builds?limit=25";
+        assert_eq!(PageOptions::default().first_page(url).unwrap(), url)
+    }
+
+    #[test]
+    fn first_page_applies_hints() {
+        let options = PageOptions::builder()
+            .offset(50)
+            .page_size(100)
+            .max_items(10)
+            .build()
+            .unwrap();
+        assert_eq!(
+            options
+                .first_page(
+                    "https://api.travis-ci.org/repo/foo%2Fbar/builds?limit=25&sort_by=id",
+                )
+                .unwrap(),
+            "https://api.travis-ci.org/repo/foo%2Fbar/builds?sort_by=id&limit=10&offset=50"
+        )
+    }
+}
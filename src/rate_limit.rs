@@ -0,0 +1,266 @@
+//! Rate limit reporting and client-side throttling
+
+use {Error, Future, Response};
+use futures::{Future as StdFuture, future};
+use futures::unsync::oneshot;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+header! {
+    #[doc(hidden)]
+    (RateLimitLimit, "X-RateLimit-Limit") => [u64]
+}
+
+header! {
+    #[doc(hidden)]
+    (RateLimitRemaining, "X-RateLimit-Remaining") => [u64]
+}
+
+header! {
+    #[doc(hidden)]
+    (RateLimitReset, "X-RateLimit-Reset") => [u64]
+}
+
+/// Rate limit status reported by travis with a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// number of requests permitted in the current window
+    pub limit: Option<u64>,
+    /// number of requests remaining in the current window
+    pub remaining: Option<u64>,
+    /// when the current window resets
+    pub reset: Option<SystemTime>,
+}
+
+impl RateLimit {
+    /// extracts rate limit headers from a response, if present
+    pub(crate) fn from_response(response: &Response) -> Option<Self> {
+        let headers = &response.headers;
+        let limit = headers.get::<RateLimitLimit>().map(|h| h.0);
+        let remaining = headers.get::<RateLimitRemaining>().map(|h| h.0);
+        let reset = headers.get::<RateLimitReset>().map(|h| {
+            UNIX_EPOCH + Duration::from_secs(h.0)
+        });
+        if limit.is_none() && remaining.is_none() && reset.is_none() {
+            None
+        } else {
+            Some(RateLimit {
+                limit,
+                remaining,
+                reset,
+            })
+        }
+    }
+
+    /// true when no requests remain in the current window
+    pub fn exhausted(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// time until the current window resets
+    pub fn reset_in(&self) -> Option<Duration> {
+        self.reset.map(|reset| {
+            reset.duration_since(SystemTime::now()).unwrap_or_else(|_| {
+                Duration::from_secs(0)
+            })
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// A client-side token bucket which delays requests so that no more
+/// than `capacity` are sent in a burst and they are sent, on average,
+/// no faster than `capacity` per `period`
+///
+/// Throttles may be shared between clients by cloning them.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    capacity: f64,
+    per_second: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing bursts of up to `capacity` requests,
+    /// refilled at a rate of `capacity` requests per `period`
+    ///
+    /// `Throttle::new(60, Duration::from_secs(60))` sends at most 60
+    /// requests at once and one a second on average after that.
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        let capacity = f64::from(capacity.max(1));
+        let period = period.as_secs() as f64 +
+            f64::from(period.subsec_nanos()) / 1e9;
+        Throttle {
+            capacity,
+            per_second: capacity / period.max(1e-9),
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                refilled: Instant::now(),
+            })),
+        }
+    }
+
+    /// reserves a token, returning how long to wait before using it
+    pub(crate) fn reserve(&self) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled);
+        let elapsed = elapsed.as_secs() as f64 +
+            f64::from(elapsed.subsec_nanos()) / 1e9;
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second)
+            .min(self.capacity);
+        bucket.refilled = now;
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            None
+        } else {
+            let wait = -bucket.tokens / self.per_second;
+            Some(Duration::new(
+                wait.trunc() as u64,
+                (wait.fract() * 1e9) as u32,
+            ))
+        }
+    }
+}
+
+/// Bounds how many requests a client, and its clones, have in flight
+///
+/// Requests beyond the bound wait, in order, for one in flight to finish.
+#[derive(Debug, Clone)]
+pub(crate) struct Slots {
+    queue: Rc<RefCell<Queue>>,
+}
+
+#[derive(Debug)]
+struct Queue {
+    available: usize,
+    waiting: VecDeque<oneshot::Sender<Slot>>,
+}
+
+/// A request's claim on one of a client's `Slots`, released when dropped
+#[derive(Debug)]
+pub(crate) struct Slot {
+    queue: Rc<RefCell<Queue>>,
+}
+
+impl Slots {
+    pub(crate) fn new(size: usize) -> Self {
+        Slots {
+            queue: Rc::new(RefCell::new(Queue {
+                available: size.max(1),
+                waiting: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// resolves once a slot is free
+    pub(crate) fn claim(&self) -> Future<Slot> {
+        let mut queue = self.queue.borrow_mut();
+        if queue.available > 0 {
+            queue.available -= 1;
+            return Box::new(future::ok(Slot {
+                queue: self.queue.clone(),
+            }));
+        }
+        let (sender, receiver) = oneshot::channel();
+        queue.waiting.push_back(sender);
+        Box::new(receiver.map_err(|_| Error::from("request slots dropped")))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let waiting = self.queue.borrow_mut().waiting.pop_front();
+        match waiting {
+            // handed to the next waiting request, or dropped again, and so
+            // offered to the one after, if that request was abandoned
+            Some(sender) => {
+                let _ = sender.send(Slot {
+                    queue: self.queue.clone(),
+                });
+            }
+            None => self.queue.borrow_mut().available += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{Error, ErrorKind};
+    use testing::{Canned, client, serve};
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn rate_limit_headers_are_exposed() {
+        let (host, _) = serve(vec![
+            Canned::new(200, r#"{"env_vars": []}"#)
+                .header("X-RateLimit-Limit", "5000")
+                .header("X-RateLimit-Remaining", "4999")
+                .header("X-RateLimit-Reset", "1500000000"),
+        ]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core);
+        assert_eq!(travis.rate_limit(), None);
+        core.run(travis.env("foo/bar").vars()).unwrap();
+        assert_eq!(
+            travis.rate_limit(),
+            Some(RateLimit {
+                limit: Some(5000),
+                remaining: Some(4999),
+                reset: Some(UNIX_EPOCH + Duration::from_secs(1500000000)),
+            })
+        );
+    }
+
+    #[test]
+    fn too_many_requests_are_rate_limited() {
+        let (host, _) = serve(vec![
+            Canned::new(429, "<html>slow down</html>")
+                .header("Retry-After", "2"),
+        ]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core);
+        match core.run(travis.env("foo/bar").vars()) {
+            Err(Error(ErrorKind::RateLimited { retry_after }, _)) => {
+                assert_eq!(retry_after, Some(Duration::from_secs(2)))
+            }
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn throttle_permits_bursts_up_to_capacity() {
+        let throttle = Throttle::new(2, Duration::from_secs(60));
+        assert_eq!(throttle.reserve(), None);
+        assert_eq!(throttle.reserve(), None);
+        let wait = throttle.reserve().unwrap();
+        assert!(wait > Duration::from_secs(29));
+        assert!(wait <= Duration::from_secs(30));
+        let wait = throttle.reserve().unwrap();
+        assert!(wait > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn slots_are_handed_to_waiting_requests_in_order() {
+        let slots = Slots::new(1);
+        let first = slots.claim().wait().unwrap();
+        let abandoned = slots.claim();
+        let mut second = slots.claim();
+        drop(abandoned);
+        drop(first);
+        let second = second.poll().unwrap();
+        assert!(second.is_ready());
+        assert_eq!(slots.queue.borrow().available, 0);
+        drop(second);
+        assert_eq!(slots.queue.borrow().available, 1);
+    }
+}
@@ -0,0 +1,277 @@
+//! Driving clients from other threads
+//!
+//! A `Client` is bound to the reactor it was created on, so neither it
+//! nor its futures may leave that thread. A `RemoteClient` is a `Send +
+//! Sync` handle which forwards operations to a `Client` running on its
+//! reactor, resolving to `SendFuture`s and `SendStream`s which may be
+//! spawned onto thread pools or held in `'static` tasks.
+//!
+//! ```no_run
+//! extern crate futures;
+//! extern crate tokio_core;
+//! extern crate travis;
+//!
+//! use futures::Future;
+//! use std::thread;
+//! use tokio_core::reactor::Core;
+//! use travis::{Client, RemoteClient};
+//!
+//! fn main() {
+//!   let core = Core::new().unwrap();
+//!   let travis = RemoteClient::spawn(&core.remote(), |handle| {
+//!     Client::oss_async(None, handle)
+//!   });
+//!   thread::spawn(move || {
+//!     let vars = travis.run(|travis| travis.env("softprops/travis").vars());
+//!     println!("{:?}", vars.wait().map(|vars| vars.len()));
+//!   });
+//!   // drive the client's requests
+//!   let mut core = core;
+//!   core.run(futures::future::empty::<(), ()>()).unwrap();
+//! }
+//! ```
+
+use {Client, Future, SendFuture, SendStream, Stream};
+use error::{Error, ErrorKind};
+use futures::{Future as StdFuture, Sink, Stream as StdStream, future};
+use futures::sync::{mpsc, oneshot};
+use hyper::client::Connect;
+use std::fmt;
+use tokio_core::reactor::{Handle, Remote};
+
+/// an operation to perform with the remote client on its reactor
+type Job<C> = Box<FnMut(&Client<C>, &Handle) + Send>;
+
+/// A `Send + Sync` handle to a `Client` running on another thread's
+/// reactor
+///
+/// Remote clients may be cloned. The client stops once its reactor does,
+/// failing outstanding and future operations with `ErrorKind::Stopped`.
+pub struct RemoteClient<C>
+where
+    C: Clone + Connect,
+{
+    jobs: mpsc::UnboundedSender<Job<C>>,
+}
+
+impl<C> Clone for RemoteClient<C>
+where
+    C: Clone + Connect,
+{
+    fn clone(&self) -> Self {
+        RemoteClient { jobs: self.jobs.clone() }
+    }
+}
+
+impl<C> fmt::Debug for RemoteClient<C>
+where
+    C: Clone + Connect,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("RemoteClient")
+    }
+}
+
+impl<C> RemoteClient<C>
+where
+    C: Clone + Connect,
+{
+    /// Creates a client on `remote`'s reactor with `client`
+    ///
+    /// Operations are queued until the client is created. If creating it
+    /// fails they fail with `ErrorKind::Stopped`.
+    pub fn spawn<F>(remote: &Remote, client: F) -> Self
+    where
+        F: FnOnce(&Handle) -> Future<Client<C>> + Send + 'static,
+    {
+        let (jobs, queued) = mpsc::unbounded();
+        remote.spawn(move |handle| {
+            let handle = handle.clone();
+            client(&handle)
+                .map_err(|err| warn!("failed to create client: {}", err))
+                .and_then(move |client| serve(client, handle, queued))
+        });
+        RemoteClient { jobs }
+    }
+
+    /// Resolves to the outcome of `operation` performed with the client
+    pub fn run<F, T>(&self, operation: F) -> SendFuture<T>
+    where
+        F: FnOnce(&Client<C>) -> Future<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let mut pending = Some((operation, tx));
+        let job: Job<C> = Box::new(move |client, handle| {
+            if let Some((operation, tx)) = pending.take() {
+                handle.spawn(operation(client).then(move |result| {
+                    let _ = tx.send(result);
+                    Ok(())
+                }))
+            }
+        });
+        if self.jobs.unbounded_send(job).is_err() {
+            return Box::new(future::err(ErrorKind::Stopped.into()));
+        }
+        Box::new(rx.then(|result| match result {
+            Ok(result) => result,
+            Err(_) => Err(ErrorKind::Stopped.into()),
+        }))
+    }
+
+    /// Streams the items of `operation` performed with the client
+    ///
+    /// Items are buffered without bound until they're consumed
+    pub fn stream<F, T>(&self, operation: F) -> SendStream<T>
+    where
+        F: FnOnce(&Client<C>) -> Stream<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let mut pending = Some((operation, tx));
+        let job: Job<C> = Box::new(move |client, handle| {
+            if let Some((operation, tx)) = pending.take() {
+                handle.spawn(
+                    operation(client)
+                        .then(Ok::<_, ()>)
+                        .forward(tx.sink_map_err(|_| ()))
+                        .map(|_| ()),
+                )
+            }
+        });
+        if self.jobs.unbounded_send(job).is_err() {
+            return Box::new(
+                future::err(ErrorKind::Stopped.into()).into_stream(),
+            );
+        }
+        Box::new(rx.then(|item| match item {
+            Ok(item) => item,
+            Err(()) => Err(ErrorKind::Stopped.into()),
+        }))
+    }
+}
+
+impl<C> Client<C>
+where
+    C: Clone + Connect,
+{
+    /// A `Send + Sync` handle to this client, which performs operations on
+    /// the reactor this client was created with
+    pub fn into_remote(self) -> RemoteClient<C> {
+        let (jobs, queued) = mpsc::unbounded();
+        let handle = self.handle.clone();
+        handle.spawn(serve(self, handle.clone(), queued));
+        RemoteClient { jobs }
+    }
+}
+
+/// performs queued jobs with `client` until every `RemoteClient` is dropped
+fn serve<C>(
+    client: Client<C>,
+    handle: Handle,
+    queued: mpsc::UnboundedReceiver<Job<C>>,
+) -> Box<StdFuture<Item = (), Error = ()>>
+where
+    C: Clone + Connect,
+{
+    Box::new(queued.for_each(move |mut job| {
+        job(&client, &handle);
+        Ok(())
+    }))
+}
+
+/// errors are sent between threads along with the results of remote
+/// operations, and remote clients are shared between them
+#[allow(dead_code)]
+fn assert_thread_safe() {
+    fn send<T: Send>() {}
+    fn sync<T: Send + Sync>() {}
+    send::<Error>();
+    sync::<RemoteClient<::hyper::client::HttpConnector>>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use hyper::client::HttpConnector;
+    use std::sync::mpsc as std_mpsc;
+    use std::thread;
+    use testing::{Canned, client, serve as serve_canned};
+    use tokio_core::reactor::Core;
+
+    const VARS: &str = r#"{"env_vars": [
+      {"id": "1", "name": "A", "public": true, "value": "a",
+       "@permissions": {"read": true, "write": true}},
+      {"id": "2", "name": "B", "public": true, "value": "b",
+       "@permissions": {"read": true, "write": true}}
+    ]}"#;
+
+    /// runs a client for `host` on a background reactor
+    fn remote(host: String) -> RemoteClient<HttpConnector> {
+        let (tx, rx) = std_mpsc::channel();
+        thread::spawn(move || {
+            let mut core = Core::new().unwrap();
+            let travis = client(&host, &mut core);
+            tx.send(travis.into_remote()).unwrap();
+            core.run(future::empty::<(), ()>()).unwrap();
+        });
+        rx.recv().unwrap()
+    }
+
+    #[test]
+    fn remote_operations_resolve_on_other_threads() {
+        let (host, requests) = serve_canned(vec![Canned::new(200, VARS)]);
+        let travis = remote(host);
+        let names = thread::spawn(move || {
+            travis
+                .run(|travis| travis.env("foo/bar").vars())
+                .wait()
+                .unwrap()
+                .into_iter()
+                .map(|var| var.name.unwrap())
+                .collect::<Vec<_>>()
+        }).join()
+            .unwrap();
+        assert_eq!(names, vec!["A", "B"]);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["GET /repo/foo%2Fbar/env_vars"]
+        );
+    }
+
+    #[test]
+    fn remote_streams_forward_items() {
+        let (host, _) = serve_canned(vec![Canned::new(200, VARS)]);
+        let travis = remote(host);
+        let ids = travis
+            .stream(|travis| {
+                Box::new(
+                    travis
+                        .env("foo/bar")
+                        .vars()
+                        .map(stream::iter_ok)
+                        .flatten_stream(),
+                )
+            })
+            .map(|var| var.id)
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(ids, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn failed_clients_stop() {
+        let core = Core::new().unwrap();
+        let travis: RemoteClient<HttpConnector> =
+            RemoteClient::spawn(&core.remote(), |_| {
+                Box::new(future::err(ErrorKind::Stopped.into()))
+            });
+        let mut core = core;
+        match core.run(travis.run(|travis| travis.env("foo/bar").vars())) {
+            Err(Error(ErrorKind::Stopped, _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+    }
+}
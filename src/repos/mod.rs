@@ -1,9 +1,13 @@
 //! Interfaces for interacting with travis repositories
 
-use {Branch, Client, Error, Stream, Future, Owner, Pagination};
-use futures::{future, stream, Future as StdFuture, IntoFuture, Stream as StdStream};
+use {Branch, Client, Embedded, Future, Minimal, Owner, Stream, escape};
+use futures::{future, Future as StdFuture};
+use builds::{Build, MinimalBuild};
+use hyper::Uri;
 use hyper::client::Connect;
+use pagination::{PageOptions, Paginated, Pagination};
 use std::borrow::Cow;
+use std::fmt;
 
 use url::form_urlencoded::Serializer;
 
@@ -14,10 +18,58 @@ struct Wrapper {
     pagination: Pagination,
 }
 
-/// A travis repository
+impl Paginated for Wrapper {
+    type Item = Repository;
+
+    fn pagination(&self) -> &Pagination {
+        &self.pagination
+    }
+
+    fn into_items(self) -> Vec<Repository> {
+        self.repositories
+    }
+}
+
+/// A repository's minimal representation, as embedded in other resources
+#[derive(Debug, Deserialize, Clone)]
+pub struct MinimalRepository {
+    pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
+    pub name: String,
+    pub slug: String,
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
+}
+
+impl Minimal for MinimalRepository {
+    type Standard = Repository;
+
+    const STANDARD_FIELDS: &'static [&'static str] = &[
+        "description",
+        "github_language",
+        "active",
+        "private",
+        "owner",
+        "@permissions",
+        "default_branch",
+        "starred",
+        "current_build",
+    ];
+
+    fn path(&self) -> String {
+        self.href.clone().unwrap_or_else(|| format!("/repo/{}", self.id))
+    }
+}
+
+/// A travis repository, in its standard representation
 #[derive(Debug, Deserialize, Clone)]
 pub struct Repository {
     pub id: usize,
+    #[serde(rename = "@href", default)]
+    pub href: Option<String>,
     pub name: String,
     pub slug: String,
     pub description: Option<String>,
@@ -29,6 +81,13 @@ pub struct Repository {
     pub permissions: RepoPermissions,
     pub default_branch: Option<Branch>,
     pub starred: bool,
+    /// standard when listed with `Include::CurrentBuild`
+    #[serde(default)]
+    pub current_build: Option<Embedded<MinimalBuild, Build>>,
+    /// fields not yet modeled by this crate
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: ::Extra,
 }
 
 /// Permissions associated with this repository
@@ -48,11 +107,56 @@ pub struct RepoPermissions {
     pub create_request: bool,
 }
 
+/// Embedded resources which may be included with listed repositories
+#[derive(Debug, Clone, PartialEq)]
+pub enum Include {
+    /// `repository.current_build`
+    CurrentBuild,
+    /// `repository.default_branch`
+    DefaultBranch,
+    /// `repository.owner`
+    Owner,
+    /// any other include, passed through as is
+    Raw(String),
+}
+
+impl<'a> From<&'a str> for Include {
+    fn from(include: &'a str) -> Self {
+        match include {
+            "repository.current_build" => Include::CurrentBuild,
+            "repository.default_branch" => Include::DefaultBranch,
+            "repository.owner" => Include::Owner,
+            other => Include::Raw(other.to_owned()),
+        }
+    }
+}
+
+impl From<String> for Include {
+    fn from(include: String) -> Self {
+        Include::from(include.as_ref())
+    }
+}
+
+impl fmt::Display for Include {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match *self {
+                Include::CurrentBuild => "repository.current_build",
+                Include::DefaultBranch => "repository.default_branch",
+                Include::Owner => "repository.owner",
+                Include::Raw(ref include) => include,
+            }
+        )
+    }
+}
+
 /// Repository list options
-#[derive(Builder, Debug)]
+#[derive(Builder, Debug, Clone)]
 #[builder(setter(into), default)]
 pub struct ListOptions {
-    include: Vec<String>,
+    include: Vec<Include>,
     limit: i32,
     /// id, started_at, finished_at,
     /// append :desc to any attribute to reverse order.
@@ -69,7 +173,14 @@ impl ListOptions {
 
     fn into_query_string(&self) -> String {
         let mut params = vec![
-            ("include", self.include.join(",")),
+            (
+                "include",
+                self.include
+                    .iter()
+                    .map(Include::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
             ("limit", self.limit.to_string()),
             ("sort_by", self.sort_by.clone()),
         ];
@@ -124,16 +235,7 @@ where
     {
         Box::new(
             self.travis
-                .get(
-                    format!(
-                        "{host}/owner/{owner}/repos??{query}",
-                        host = self.travis.host,
-                        owner = owner.into().as_ref(),
-                        query = options.into_query_string()
-                    ).parse()
-                        .map_err(Error::from)
-                        .into_future(),
-                )
+                .get(self.locate(&owner.into(), options))
                 .and_then(|wrapper: Wrapper| future::ok(wrapper.repositories)),
         )
     }
@@ -146,70 +248,103 @@ where
     where
         O: Into<String>,
     {
-        let first = self.travis
-            .get::<Wrapper>(
-                format!(
-                    "{host}/owner/{owner}/repos?{query}",
-                    host = self.travis.host,
-                    owner = owner.into().clone(),
-                    query = options.into_query_string()
-                ).parse()
-                    .map_err(Error::from)
-                    .into_future(),
-            )
-            .map(|mut wrapper: Wrapper| {
-                let mut repositories = wrapper.repositories;
-                repositories.reverse();
-                wrapper.repositories = repositories;
-                wrapper
-            });
-        // needed to move "self" into the closure below
-        let clone = self.clone();
-        Box::new(
-            first
-                .map(move |wrapper| {
-                    stream::unfold::<_, _, Future<(Repository, Wrapper)>, _>(
-                        wrapper,
-                        move |mut state| match state.repositories.pop() {
-                            Some(repository) => Some(Box::new(
-                                future::ok((repository, state)),
-                            )),
-                            _ => {
-                                state.pagination.next.clone().map(|path| {
-                                    Box::new(
-                                        clone
-                                            .travis
-                                            .get::<Wrapper>(
-                                                format!(
-                                                    "{host}{path}",
-                                                    host = clone.travis.host,
-                                                    path = path.href
-                                                ).parse()
-                                                    .map_err(Error::from)
-                                                    .into_future(),
-                                            )
-                                            .map(|mut next| {
-                                                let mut repositories =
-                                                    next.repositories;
-                                                repositories.reverse();
-                                                next.repositories =
-                                                    repositories;
-                                                (
-                                                    next.repositories
-                                                        .pop()
-                                                        .unwrap(),
-                                                    next,
-                                                )
-                                            }),
-                                    ) as
-                                        Future<(Repository, Wrapper)>
-                                })
-                            }
-                        },
-                    )
-                })
-                .into_stream()
-                .flatten(),
+        self.iter_with(owner, options, &PageOptions::default())
+    }
+
+    /// streams repositories across pages, requesting them as described
+    /// by `paging`
+    pub fn iter_with<O>(
+        &self,
+        owner: O,
+        options: &ListOptions,
+        paging: &PageOptions,
+    ) -> Stream<Repository>
+    where
+        O: Into<String>,
+    {
+        self.travis
+            .paginate::<Wrapper>(self.locate(&owner.into(), options), paging)
+    }
+
+    /// the uri of `owner`'s repositories
+    fn locate(&self, owner: &str, options: &ListOptions) -> Future<Uri> {
+        self.travis.locate(
+            "repositories",
+            "for_owner",
+            &[("owner.login", owner)],
+            format!("/owner/{}/repos", escape(owner)),
+            options.into_query_string(),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use State;
+    use serde_json;
+
+    fn repository(current_build: &str) -> Repository {
+        serde_json::from_str::<Repository>(&format!(
+            r#"{{
+              "id": 1, "name": "travis", "slug": "softprops/travis",
+              "description": null, "github_language": "rust",
+              "active": true, "private": false, "starred": false,
+              "owner": {{ "id": 2, "login": "softprops" }},
+              "@permissions": {{
+                "read": true, "admin": false, "activate": false,
+                "deactivate": false, "star": true, "unstar": true,
+                "create_cron": false, "create_env_var": false,
+                "create_key_pair": false, "delete_key_pair": false,
+                "create_request": false
+              }},
+              "default_branch": {{ "name": "master" }},
+              "current_build": {}
+            }}"#,
+            current_build
+        )).unwrap()
+    }
+
+    #[test]
+    fn current_build_representations_are_distinguished() {
+        match repository(
+            r#"{
+              "@representation": "minimal", "id": 3, "number": "7",
+              "state": "passed", "duration": 60, "event_type": "push",
+              "previous_state": "failed",
+              "started_at": "2017-06-27T14:02:30Z",
+              "finished_at": "2017-06-27T14:03:30Z"
+            }"#,
+        ).current_build {
+            Some(Embedded::Minimal(ref build)) => {
+                assert_eq!(build.id, 3);
+                assert_eq!(build.number, "7");
+                assert_eq!(build.previous_state, Some(State::Failed));
+            }
+            ref otherwise => panic!("unexpected {:?}", otherwise),
+        }
+        assert!(repository("null").current_build.is_none());
+        let build = repository(
+            r#"{
+              "@representation": "standard", "id": 3, "number": "7",
+              "state": "passed", "duration": 60, "event_type": "push",
+              "previous_state": "failed", "pull_request_title": null,
+              "pull_request_number": null,
+              "started_at": "2017-06-27T14:02:30Z",
+              "finished_at": "2017-06-27T14:03:30Z",
+              "branch": { "name": "master" },
+              "commit": { "id": 4, "sha": "abc123", "ref": "refs/heads/master" },
+              "jobs": [{ "id": 5 }],
+              "created_by": { "id": 2, "login": "softprops" }
+            }"#,
+        ).current_build
+            .and_then(Embedded::into_standard)
+            .unwrap();
+        assert_eq!(build.id, 3);
+        match build.jobs[0] {
+            Embedded::Minimal(ref job) => assert_eq!(job.path(), "/job/5"),
+            ref otherwise => panic!("unexpected {:?}", otherwise),
+        }
+        assert_eq!(build.commit.unwrap().sha, Some("abc123".into()));
+    }
+}
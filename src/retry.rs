@@ -0,0 +1,343 @@
+//! Retrying requests which fail for transient reasons
+
+use {Error, Future, Response};
+use error::ErrorKind;
+use futures::{Future as StdFuture, IntoFuture, future};
+use futures::future::Loop;
+use hyper::{Method, StatusCode};
+use hyper::header::RetryAfter;
+use rand::{self, Rng};
+use std::cmp;
+use std::time::{Duration, SystemTime};
+use tokio_core::reactor::{Handle, Timeout};
+
+/// Policy governing how requests which fail for transient reasons,
+/// like connection resets or 5xx responses, are retried
+///
+/// The default policy makes up to 3 attempts, backing off exponentially
+/// from half a second, and only retries idempotent methods.
+#[derive(Builder, Clone, Debug)]
+#[builder(setter(into), default)]
+pub struct RetryPolicy {
+    /// total number of attempts, including the first
+    max_attempts: u32,
+    /// delay before the first retry, doubled for each following retry
+    base_delay: Duration,
+    /// upper bound on the delay between attempts
+    max_delay: Duration,
+    /// randomize delays so concurrent clients don't retry in lockstep
+    jitter: bool,
+    /// also retry methods which are not idempotent, like `POST`
+    retry_non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    /// Creates a builder starting from the default policy
+    pub fn builder() -> RetryPolicyBuilder {
+        RetryPolicyBuilder::default()
+    }
+
+    /// A policy which never retries
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// delay before the attempt following `attempt`
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay
+            .checked_mul(1 << cmp::min(attempt - 1, 16))
+            .unwrap_or(self.max_delay);
+        let delay = cmp::min(exp, self.max_delay);
+        if self.jitter {
+            // "equal jitter": keep half the delay, randomize the rest
+            let half = delay / 2;
+            let millis = half.as_secs() * 1000 +
+                u64::from(half.subsec_nanos() / 1_000_000);
+            half +
+                Duration::from_millis(
+                    rand::thread_rng().gen_range(0, millis + 1),
+                )
+        } else {
+            delay
+        }
+    }
+
+    fn permits(&self, method: &Method) -> bool {
+        self.retry_non_idempotent || idempotent(method)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+fn idempotent(method: &Method) -> bool {
+    match *method {
+        Method::Get | Method::Head | Method::Put | Method::Delete |
+        Method::Options | Method::Trace => true,
+        _ => false,
+    }
+}
+
+fn transient_status(status: StatusCode) -> bool {
+    match status {
+        StatusCode::TooManyRequests |
+        StatusCode::InternalServerError |
+        StatusCode::BadGateway |
+        StatusCode::ServiceUnavailable |
+        StatusCode::GatewayTimeout => true,
+        _ => false,
+    }
+}
+
+fn transient_error(error: &Error) -> bool {
+    use hyper::Error as HttpError;
+    match *error.kind() {
+        ErrorKind::IO(_) => true,
+        ErrorKind::Http(HttpError::Io(_)) |
+        ErrorKind::Http(HttpError::Timeout) |
+        ErrorKind::Http(HttpError::Incomplete) |
+        ErrorKind::Http(HttpError::Closed) => true,
+        _ => false,
+    }
+}
+
+/// delay requested by a `Retry-After` header, if any
+pub(crate) fn retry_after(response: &Response) -> Option<Duration> {
+    match response.headers.get::<RetryAfter>() {
+        Some(&RetryAfter::Delay(delay)) => Some(delay),
+        Some(&RetryAfter::DateTime(date)) => {
+            SystemTime::from(date)
+                .duration_since(SystemTime::now())
+                .ok()
+                .or_else(|| Some(Duration::from_secs(0)))
+        }
+        None => None,
+    }
+}
+
+/// Resolves the responses of successive attempts until one succeeds,
+/// fails for a reason which isn't transient, or `policy` is exhausted
+///
+/// Non-success responses resolve as errors.
+pub(crate) fn retry<F>(
+    policy: RetryPolicy,
+    handle: Handle,
+    method: Method,
+    url: String,
+    attempt: F,
+) -> Future<Response>
+where
+    F: Fn() -> Future<Response> + 'static,
+{
+    Box::new(future::loop_fn(1, move |n| {
+        let policy = policy.clone();
+        let handle = handle.clone();
+        let method = method.clone();
+        let url = url.clone();
+        attempt().then(move |result| -> Future<Loop<Response, u32>> {
+            let (error, delay) = match result {
+                Ok(response) => {
+                    if response.status.is_success() {
+                        return Box::new(future::ok(Loop::Break(response)));
+                    }
+                    let transient = transient_status(response.status);
+                    let delay = retry_after(&response);
+                    let error = response.into_error();
+                    if !transient {
+                        return Box::new(future::err(error));
+                    }
+                    (error, delay)
+                }
+                Err(error) => {
+                    if !transient_error(&error) {
+                        return Box::new(future::err(error));
+                    }
+                    (error, None)
+                }
+            };
+            if n >= policy.max_attempts || !policy.permits(&method) {
+                if n > 1 {
+                    warn!("{} {} failed after {} attempts", method, url, n);
+                    return Box::new(future::err(error.exhausted(n)));
+                }
+                return Box::new(future::err(error));
+            }
+            // a server's delay is bounded like ours, so one bad header
+            // can't stall a request for hours
+            let delay = delay.map_or_else(
+                || policy.backoff(n),
+                |delay| cmp::min(delay, policy.max_delay),
+            );
+            warn!(
+                "{} {} attempt {}/{} failed ({}), retrying in {:?}",
+                method,
+                url,
+                n,
+                policy.max_attempts,
+                error,
+                delay
+            );
+            Box::new(
+                Timeout::new(delay, &handle)
+                    .into_future()
+                    .flatten()
+                    .map(move |_| Loop::Continue(n + 1))
+                    .map_err(Error::from),
+            )
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use env::EnvVarCreate;
+    use error::ApiError;
+    use testing::{Canned, client, serve};
+    use tokio_core::reactor::Core;
+
+    fn quick() -> RetryPolicy {
+        RetryPolicy::builder()
+            .jitter(false)
+            .base_delay(Duration::from_millis(10))
+            .build()
+            .unwrap()
+    }
+
+    const VARS: &str = r#"{"env_vars": []}"#;
+    const UNAVAILABLE: &str = r#"{"error_message": "unavailable"}"#;
+
+    #[test]
+    fn transient_failures_are_retried() {
+        let (host, requests) = serve(vec![
+            Canned::new(503, UNAVAILABLE),
+            Canned::new(502, "<html>bad gateway</html>"),
+            Canned::new(200, VARS),
+        ]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core).with_retry_policy(quick());
+        let vars = core.run(travis.env("foo/bar").vars()).unwrap();
+        assert!(vars.is_empty());
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn exhausted_retries_report_attempts() {
+        let (host, _) = serve(vec![
+            Canned::new(503, UNAVAILABLE),
+            Canned::new(503, UNAVAILABLE),
+            Canned::new(503, UNAVAILABLE),
+        ]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core).with_retry_policy(quick());
+        match core.run(travis.env("foo/bar").vars()) {
+            Err(ref error @ Error(ErrorKind::Api(ApiError::Other(_)), _)) => {
+                assert_eq!(error.attempts(), Some(3))
+            }
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn retry_after_is_honored() {
+        let (host, _) = serve(vec![
+            Canned::new(503, UNAVAILABLE).header("Retry-After", "1"),
+            Canned::new(200, VARS),
+        ]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core).with_retry_policy(quick());
+        let started = ::std::time::Instant::now();
+        core.run(travis.env("foo/bar").vars()).unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retry_after_is_bounded_by_max_delay() {
+        let (host, requests) = serve(vec![
+            Canned::new(503, UNAVAILABLE).header("Retry-After", "3600"),
+            Canned::new(200, VARS),
+        ]);
+        let mut core = Core::new().unwrap();
+        let policy = RetryPolicy::builder()
+            .jitter(false)
+            .max_delay(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let travis = client(&host, &mut core).with_retry_policy(policy);
+        let started = ::std::time::Instant::now();
+        core.run(travis.env("foo/bar").vars()).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn non_idempotent_requests_are_not_retried() {
+        let (host, requests) = serve(vec![
+            Canned::new(503, UNAVAILABLE),
+            Canned::new(200, VARS),
+        ]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&host, &mut core).with_retry_policy(quick());
+        let result = core.run(travis.env("foo/bar").set(EnvVarCreate {
+            name: "FOO".into(),
+            value: "bar".into(),
+            public: false,
+        }));
+        match result {
+            Err(Error(ErrorKind::Api(_), _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec!["POST /repo/foo%2Fbar/env_vars"]
+        );
+    }
+
+    #[test]
+    fn backoff_is_exponential_and_bounded() {
+        let policy = RetryPolicy::builder()
+            .jitter(false)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(350))
+            .build()
+            .unwrap();
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(350));
+        assert_eq!(policy.backoff(40), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn jitter_keeps_half_the_delay() {
+        let policy = RetryPolicy::builder()
+            .base_delay(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        for _ in 0..20 {
+            let delay = policy.backoff(1);
+            assert!(delay >= Duration::from_millis(50));
+            assert!(delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn only_idempotent_methods_are_retried_by_default() {
+        let policy = RetryPolicy::default();
+        assert!(policy.permits(&Method::Get));
+        assert!(policy.permits(&Method::Delete));
+        assert!(!policy.permits(&Method::Post));
+        assert!(!policy.permits(&Method::Patch));
+    }
+}
@@ -0,0 +1,1223 @@
+//! A stateful, in-process fake of the travis v3 API for integration tests
+//!
+//! A `Server` listens on a local port and implements the endpoints this
+//! crate covers: the home document, the github token exchange,
+//! repositories, builds, jobs, env vars and build requests.
+//! Clients created with `Client::custom` for its `url` behave as they
+//! would against travis.
+//!
+//! Repositories are registered with `Server::repo`. Builds are created by
+//! triggering requests, either through the API or `Server::trigger`, and
+//! move between states as scripted with `Server::transition` and
+//! `Server::script`.
+//!
+//! ```no_run
+//! extern crate hyper;
+//! extern crate tokio_core;
+//! extern crate travis;
+//!
+//! use hyper::Client as HyperClient;
+//! use tokio_core::reactor::Core;
+//! use travis::{Client, State};
+//! use travis::server::Server;
+//!
+//! fn main() {
+//!   let server = Server::start().unwrap();
+//!   server.repo("softprops/travis");
+//!   let build = server.trigger("softprops/travis", "master").unwrap();
+//!   server.script(build, vec![State::Started, State::Passed]);
+//!
+//!   let mut core = Core::new().unwrap();
+//!   let http = HyperClient::new(&core.handle());
+//!   let travis = Client::custom(server.url(), http, None, &mut core)
+//!     .unwrap();
+//!   let builds = travis.builds("softprops/travis");
+//!   let listed = core.run(builds.list(&Default::default())).unwrap();
+//!   assert_eq!(listed[0].state, State::Started);
+//! }
+//! ```
+
+use State;
+use futures::{Future, Stream};
+use futures::sync::oneshot;
+use hyper::{self, Chunk, Method, StatusCode};
+use hyper::header::ContentType;
+use hyper::server::{Http, Request, Response, Service};
+use serde_json::{self, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Core;
+use url::form_urlencoded;
+use url::percent_encoding::percent_decode;
+
+/// The number of items travis includes in a page by default
+const DEFAULT_LIMIT: usize = 25;
+
+struct Repo {
+    id: usize,
+    slug: String,
+    owner: usize,
+    env_vars: Vec<Var>,
+}
+
+impl Repo {
+    fn owner_name(&self) -> &str {
+        self.slug.split('/').next().unwrap_or_default()
+    }
+
+    fn name(&self) -> &str {
+        self.slug.split('/').nth(1).unwrap_or_default()
+    }
+}
+
+struct Var {
+    id: String,
+    name: String,
+    value: String,
+    public: bool,
+}
+
+struct Build {
+    id: usize,
+    repo: usize,
+    number: usize,
+    job: usize,
+    branch: String,
+    event_type: String,
+    state: State,
+    previous_state: Option<State>,
+    created_at: u64,
+    started_at: Option<u64>,
+    finished_at: Option<u64>,
+    /// states to move through, one each time the build or its job is
+    /// served
+    script: VecDeque<State>,
+}
+
+impl Build {
+    fn transition(&mut self, state: State, now: u64) {
+        match state {
+            State::Received | State::Created => {
+                self.started_at = None;
+                self.finished_at = None;
+            }
+            State::Started => {
+                self.started_at = Some(now);
+                self.finished_at = None;
+            }
+            State::Passed | State::Failed | State::Errored |
+            State::Canceled => {
+                self.started_at = self.started_at.or(Some(now));
+                self.finished_at = Some(now);
+            }
+            State::Unknown(_) => (),
+        }
+        self.state = state;
+    }
+
+    fn finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+}
+
+/// Everything the server knows about
+#[derive(Default)]
+struct World {
+    repos: Vec<Repo>,
+    builds: Vec<Build>,
+    owners: BTreeMap<String, usize>,
+    requests: Vec<String>,
+    ids: usize,
+}
+
+/// an error response, represented as travis would
+fn error(status: StatusCode, error_type: &str, message: &str) -> Reply {
+    (
+        status,
+        json!({
+            "@type": "error",
+            "error_type": error_type,
+            "error_message": message,
+        }),
+    )
+}
+
+fn not_found(resource_type: &str) -> Reply {
+    let (status, mut body) = error(
+        StatusCode::NotFound,
+        "not_found",
+        &format!("{} not found (or insufficient access)", resource_type),
+    );
+    body["resource_type"] = json!(resource_type);
+    (status, body)
+}
+
+fn wrong_params(message: &str) -> Reply {
+    error(StatusCode::BadRequest, "wrong_params", message)
+}
+
+type Reply = (StatusCode, Value);
+
+impl World {
+    fn id(&mut self) -> usize {
+        self.ids += 1;
+        self.ids
+    }
+
+    fn repo(&mut self, slug: &str) -> usize {
+        if let Some(repo) = self.repos.iter().find(|repo| repo.slug == slug) {
+            return repo.id;
+        }
+        let login = slug.split('/').next().unwrap_or_default().to_owned();
+        let owner = match self.owners.get(&login) {
+            Some(&owner) => owner,
+            None => self.id(),
+        };
+        self.owners.insert(login, owner);
+        let id = self.id();
+        self.repos.push(Repo {
+            id,
+            slug: slug.to_owned(),
+            owner,
+            env_vars: Vec::new(),
+        });
+        id
+    }
+
+    fn find_repo(&mut self, slug: &str) -> Option<&mut Repo> {
+        self.repos.iter_mut().find(|repo| repo.slug == slug)
+    }
+
+    fn trigger(
+        &mut self,
+        slug: &str,
+        branch: &str,
+        event_type: &str,
+    ) -> Option<usize> {
+        let repo = self.repos.iter().find(|repo| repo.slug == slug)?.id;
+        let previous = self.builds.iter().rev().find(|build| {
+            build.repo == repo && build.finished()
+        });
+        let previous_state = previous.map(|build| build.state.clone());
+        let number = self.builds
+            .iter()
+            .filter(|build| build.repo == repo)
+            .count() + 1;
+        let (id, job) = (self.id(), self.id());
+        self.builds.push(Build {
+            id,
+            repo,
+            number,
+            job,
+            branch: branch.to_owned(),
+            event_type: event_type.to_owned(),
+            state: State::Created,
+            previous_state,
+            created_at: now(),
+            started_at: None,
+            finished_at: None,
+            script: VecDeque::new(),
+        });
+        Some(id)
+    }
+
+    fn build(&mut self, id: usize) -> Option<&mut Build> {
+        self.builds.iter_mut().find(|build| build.id == id)
+    }
+
+    /// moves `build` to the next of its scripted states
+    fn advance(&mut self, build: usize) {
+        if let Some(build) = self.build(build) {
+            if let Some(state) = build.script.pop_front() {
+                build.transition(state, now())
+            }
+        }
+    }
+
+    fn respond(
+        &mut self,
+        method: &Method,
+        path: &str,
+        query: &str,
+        body: &[u8],
+    ) -> Reply {
+        self.requests.push(if query.is_empty() {
+            format!("{} {}", method, path)
+        } else {
+            format!("{} {}?{}", method, path, query)
+        });
+        let segments = path.trim_matches('/')
+            .split('/')
+            .map(|segment| {
+                percent_decode(segment.as_bytes())
+                    .decode_utf8_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        let params = Params::new(query);
+        let body = if body.is_empty() {
+            Ok(json!({}))
+        } else {
+            serde_json::from_slice::<Value>(body)
+        };
+        let body = match body {
+            Ok(body) => body,
+            Err(_) => return wrong_params("request body is not json"),
+        };
+        match (method, segments.as_slice()) {
+            (&Method::Get, &[""]) => (StatusCode::Ok, home()),
+            (&Method::Post, &["auth", "github"]) => self.exchange(&body),
+            (&Method::Get, &["owner", owner, "repos"]) => {
+                self.list_repos(path, owner, &params)
+            }
+            (&Method::Get, &["repo", slug, "builds"]) => {
+                self.list_builds(path, slug, &params)
+            }
+            (&Method::Post, &["repo", slug, "requests"]) => {
+                self.create_request(slug, &body)
+            }
+            (&Method::Get, &["repo", repo]) => self.find_repository(repo),
+            (&Method::Get, &["build", id]) => self.find_build(id),
+            (&Method::Get, &["job", id]) => self.find_job(id),
+            (&Method::Get, &["build", id, "jobs"]) => self.list_jobs(id),
+            (&Method::Get, &["repo", slug, "env_vars"]) => self.list_vars(slug),
+            (&Method::Post, &["repo", slug, "env_vars"]) => {
+                self.create_var(slug, &body)
+            }
+            (&Method::Get, &["repo", slug, "env_var", id]) => {
+                self.get_var(slug, id)
+            }
+            (&Method::Patch, &["repo", slug, "env_var", id]) => {
+                self.update_var(slug, id, &body)
+            }
+            (&Method::Delete, &["repo", slug, "env_var", id]) => {
+                self.delete_var(slug, id)
+            }
+            _ => not_found("resource"),
+        }
+    }
+
+    fn exchange(&mut self, body: &Value) -> Reply {
+        match body["github_token"].as_str() {
+            Some(token) => {
+                (
+                    StatusCode::Ok,
+                    json!({ "access_token": format!("travis-{}", token) }),
+                )
+            }
+            None => error(
+                StatusCode::Forbidden,
+                "wrong_credentials",
+                "missing github_token",
+            ),
+        }
+    }
+
+    fn list_repos(
+        &mut self,
+        path: &str,
+        owner: &str,
+        params: &Params,
+    ) -> Reply {
+        if !self.owners.contains_key(owner) {
+            return not_found("owner");
+        }
+        // registered repositories are public, active and unstarred
+        let filtered = params.get("starred") == Some("true") ||
+            params.get("private") == Some("true") ||
+            params.get("active") == Some("false");
+        let repos = self.repos
+            .iter()
+            .filter(|repo| !filtered && repo.owner_name() == owner)
+            .map(|repo| self.render_repo(repo))
+            .collect();
+        (StatusCode::Ok, page("repositories", path, params, repos))
+    }
+
+    fn list_builds(
+        &mut self,
+        path: &str,
+        slug: &str,
+        params: &Params,
+    ) -> Reply {
+        let repo = match self.find_repo(slug) {
+            Some(repo) => repo.id,
+            None => return not_found("repository"),
+        };
+        let ids = self.builds
+            .iter()
+            .filter(|build| build.repo == repo)
+            .map(|build| build.id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.advance(id)
+        }
+        let builds = self.builds
+            .iter()
+            .rev()
+            .filter(|build| build.repo == repo)
+            .filter(|build| {
+                params.get("state").map_or(true, |state| {
+                    build.state.to_string() == state
+                })
+            })
+            .filter(|build| {
+                params.get("event_type").map_or(true, |event_type| {
+                    build.event_type == event_type
+                })
+            })
+            .map(|build| self.render_build(build))
+            .collect();
+        (StatusCode::Ok, page("builds", path, params, builds))
+    }
+
+    fn find_repository(&mut self, repo: &str) -> Reply {
+        let id = repo.parse::<usize>().ok();
+        match self.repos.iter().find(|found| {
+            Some(found.id) == id || found.slug == repo
+        }) {
+            Some(repo) => (StatusCode::Ok, self.render_repo(repo)),
+            None => not_found("repository"),
+        }
+    }
+
+    fn find_build(&mut self, id: &str) -> Reply {
+        let id = match id.parse() {
+            Ok(id) => id,
+            Err(_) => return not_found("build"),
+        };
+        self.advance(id);
+        match self.builds.iter().find(|build| build.id == id) {
+            Some(build) => (StatusCode::Ok, self.render_build(build)),
+            None => not_found("build"),
+        }
+    }
+
+    fn find_job(&mut self, id: &str) -> Reply {
+        let build = id.parse::<usize>().ok().and_then(|id| {
+            self.builds.iter().find(|build| build.job == id).map(
+                |build| build.id,
+            )
+        });
+        match build {
+            Some(build) => {
+                self.advance(build);
+                let build = self.builds
+                    .iter()
+                    .find(|found| found.id == build)
+                    .expect("advanced a missing build");
+                (StatusCode::Ok, self.render_job(build))
+            }
+            None => not_found("job"),
+        }
+    }
+
+    fn list_jobs(&mut self, id: &str) -> Reply {
+        let id = match id.parse() {
+            Ok(id) => id,
+            Err(_) => return not_found("build"),
+        };
+        self.advance(id);
+        match self.builds.iter().find(|build| build.id == id) {
+            Some(build) => {
+                (
+                    StatusCode::Ok,
+                    json!({
+                        "@type": "jobs",
+                        "@href": format!("/build/{}/jobs", id),
+                        "@representation": "standard",
+                        "jobs": [self.render_job(build)],
+                    }),
+                )
+            }
+            None => not_found("build"),
+        }
+    }
+
+    fn create_request(&mut self, slug: &str, body: &Value) -> Reply {
+        let branch = body["request"]["branch"]
+            .as_str()
+            .or_else(|| body["branch"].as_str())
+            .unwrap_or("master")
+            .to_owned();
+        let id = match self.trigger(slug, &branch, "api") {
+            Some(id) => id,
+            None => return not_found("repository"),
+        };
+        let repo = &self.repos
+            .iter()
+            .find(|repo| repo.slug == slug)
+            .expect("triggered a build for a missing repository");
+        (
+            StatusCode::Accepted,
+            json!({
+                "@type": "pending",
+                "remaining_requests": 10,
+                "repository": {
+                    "@type": "repository",
+                    "@representation": "minimal",
+                    "id": repo.id,
+                    "name": repo.name(),
+                    "slug": repo.slug,
+                },
+                "request": {
+                    "id": id,
+                    "branch": branch,
+                    "message": body["request"]["message"],
+                },
+                "resource_type": "request",
+            }),
+        )
+    }
+
+    fn list_vars(&mut self, slug: &str) -> Reply {
+        match self.find_repo(slug) {
+            Some(repo) => {
+                (
+                    StatusCode::Ok,
+                    json!({
+                        "@type": "env_vars",
+                        "@href": format!("/repo/{}/env_vars", repo.id),
+                        "@representation": "standard",
+                        "env_vars": repo.env_vars
+                            .iter()
+                            .map(|var| render_var(repo, var))
+                            .collect::<Vec<_>>(),
+                    }),
+                )
+            }
+            None => not_found("repository"),
+        }
+    }
+
+    fn create_var(&mut self, slug: &str, body: &Value) -> Reply {
+        let id = self.id();
+        let repo = match self.find_repo(slug) {
+            Some(repo) => repo,
+            None => return not_found("repository"),
+        };
+        let (name, value) = match (
+            body["env_var.name"].as_str(),
+            body["env_var.value"].as_str(),
+        ) {
+            (Some(name), Some(value)) => (name, value),
+            _ => return wrong_params("missing env_var.name or env_var.value"),
+        };
+        if repo.env_vars.iter().any(|var| var.name == name) {
+            return error(
+                StatusCode::Conflict,
+                "duplicate_resource",
+                "resource already exists",
+            );
+        }
+        repo.env_vars.push(Var {
+            id: format!("00000000-0000-4000-8000-{:012x}", id),
+            name: name.to_owned(),
+            value: value.to_owned(),
+            public: body["env_var.public"].as_bool().unwrap_or(false),
+        });
+        let var = repo.env_vars.last().expect("created env var");
+        (StatusCode::Created, render_var(repo, var))
+    }
+
+    fn get_var(&mut self, slug: &str, id: &str) -> Reply {
+        let repo = match self.find_repo(slug) {
+            Some(repo) => repo,
+            None => return not_found("repository"),
+        };
+        match repo.env_vars.iter().find(|var| var.id == id) {
+            Some(var) => (StatusCode::Ok, render_var(repo, var)),
+            None => not_found("env_var"),
+        }
+    }
+
+    fn update_var(&mut self, slug: &str, id: &str, body: &Value) -> Reply {
+        let repo = match self.find_repo(slug) {
+            Some(repo) => repo,
+            None => return not_found("repository"),
+        };
+        let at = match repo.env_vars.iter().position(|var| var.id == id) {
+            Some(at) => at,
+            None => return not_found("env_var"),
+        };
+        {
+            let var = &mut repo.env_vars[at];
+            if let Some(name) = body["env_var.name"].as_str() {
+                var.name = name.to_owned()
+            }
+            if let Some(value) = body["env_var.value"].as_str() {
+                var.value = value.to_owned()
+            }
+            if let Some(public) = body["env_var.public"].as_bool() {
+                var.public = public
+            }
+        }
+        (StatusCode::Ok, render_var(repo, &repo.env_vars[at]))
+    }
+
+    fn delete_var(&mut self, slug: &str, id: &str) -> Reply {
+        let repo = match self.find_repo(slug) {
+            Some(repo) => repo,
+            None => return not_found("repository"),
+        };
+        match repo.env_vars.iter().position(|var| var.id == id) {
+            Some(at) => {
+                repo.env_vars.remove(at);
+                (StatusCode::NoContent, Value::Null)
+            }
+            None => not_found("env_var"),
+        }
+    }
+
+    fn owner(&self, id: usize) -> Value {
+        let login = self.owners
+            .iter()
+            .find(|&(_, owner)| *owner == id)
+            .map(|(login, _)| login.clone())
+            .unwrap_or_default();
+        json!({
+            "@type": "user",
+            "@href": format!("/user/{}", id),
+            "@representation": "minimal",
+            "id": id,
+            "login": login,
+        })
+    }
+
+    fn render_repo(&self, repo: &Repo) -> Value {
+        json!({
+            "@type": "repository",
+            "@href": format!("/repo/{}", repo.id),
+            "@representation": "standard",
+            "@permissions": {
+                "read": true,
+                "admin": true,
+                "activate": true,
+                "deactivate": true,
+                "star": true,
+                "unstar": false,
+                "create_cron": true,
+                "create_env_var": true,
+                "create_key_pair": true,
+                "delete_key_pair": true,
+                "create_request": true,
+            },
+            "id": repo.id,
+            "name": repo.name(),
+            "slug": repo.slug,
+            "description": null,
+            "github_language": null,
+            "active": true,
+            "private": false,
+            "owner": self.owner(repo.owner),
+            "default_branch": {
+                "@type": "branch",
+                "@representation": "minimal",
+                "name": "master",
+            },
+            "starred": false,
+        })
+    }
+
+    fn render_build(&self, build: &Build) -> Value {
+        let repo = self.repos
+            .iter()
+            .find(|repo| repo.id == build.repo)
+            .expect("build for a missing repository");
+        json!({
+            "@type": "build",
+            "@href": format!("/build/{}", build.id),
+            "@representation": "standard",
+            "id": build.id,
+            "number": build.number.to_string(),
+            "state": build.state.to_string(),
+            "duration": duration(build),
+            "event_type": build.event_type,
+            "previous_state": build.previous_state
+                .as_ref()
+                .map(ToString::to_string),
+            "pull_request_title": null,
+            "pull_request_number": null,
+            "created_at": timestamp(build.created_at),
+            "started_at": build.started_at.map(timestamp),
+            "finished_at": build.finished_at.map(timestamp),
+            "repository": {
+                "@type": "repository",
+                "@href": format!("/repo/{}", repo.id),
+                "@representation": "minimal",
+                "id": repo.id,
+                "name": repo.name(),
+                "slug": repo.slug,
+            },
+            "branch": {
+                "@type": "branch",
+                "@representation": "minimal",
+                "name": build.branch,
+            },
+            "jobs": [{
+                "@type": "job",
+                "@href": format!("/job/{}", build.job),
+                "@representation": "minimal",
+                "id": build.job,
+            }],
+            "created_by": self.owner(repo.owner),
+        })
+    }
+
+    fn render_job(&self, build: &Build) -> Value {
+        let repo = self.repos
+            .iter()
+            .find(|repo| repo.id == build.repo)
+            .expect("job for a missing repository");
+        json!({
+            "@type": "job",
+            "@href": format!("/job/{}", build.job),
+            "@representation": "standard",
+            "id": build.job,
+            "number": format!("{}.1", build.number),
+            "state": build.state.to_string(),
+            "created_at": timestamp(build.created_at),
+            "started_at": build.started_at.map(timestamp),
+            "finished_at": build.finished_at.map(timestamp),
+            "queue": "builds.gce",
+            "owner": self.owner(repo.owner),
+        })
+    }
+}
+
+/// the home document, publishing the actions this server supports
+fn home() -> Value {
+    let template = |method: &str, uri_template: &str| {
+        json!({
+            "@type": "template",
+            "request_method": method,
+            "uri_template": uri_template,
+        })
+    };
+    let env_var = "/repo/{repository.slug}/env_var/{env_var.id}";
+    json!({
+        "@type": "home",
+        "@href": "/",
+        "resources": {
+            "repositories": { "actions": { "for_owner": [template(
+                "GET",
+                "/owner/{owner.login}/repos\
+                 {?active,include,limit,offset,private,sort_by,starred}",
+            )] } },
+            "builds": { "actions": { "find": [template(
+                "GET",
+                "/repo/{repository.slug}/builds\
+                 {?event_type,include,limit,offset,sort_by,state}",
+            )] } },
+            "repository": { "actions": { "find": [
+                template("GET", "/repo/{repository.id}"),
+                template("GET", "/repo/{repository.slug}"),
+            ] } },
+            "build": { "actions": { "find": [
+                template("GET", "/build/{build.id}"),
+            ] } },
+            "job": { "actions": { "find": [
+                template("GET", "/job/{job.id}"),
+            ] } },
+            "jobs": { "actions": { "find": [
+                template("GET", "/build/{build.id}/jobs"),
+            ] } },
+            "requests": { "actions": { "create": [
+                template("POST", "/repo/{repository.slug}/requests"),
+            ] } },
+            "env_vars": { "actions": {
+                "for_repository": [
+                    template("GET", "/repo/{repository.slug}/env_vars"),
+                ],
+                "create": [
+                    template("POST", "/repo/{repository.slug}/env_vars"),
+                ],
+            } },
+            "env_var": { "actions": {
+                "find": [template("GET", env_var)],
+                "update": [template("PATCH", env_var)],
+                "delete": [template("DELETE", env_var)],
+            } },
+        },
+    })
+}
+
+fn render_var(repo: &Repo, var: &Var) -> Value {
+    json!({
+        "@type": "env_var",
+        "@href": format!("/repo/{}/env_var/{}", repo.id, var.id),
+        "@representation": "standard",
+        "@permissions": { "read": true, "write": true },
+        "id": var.id,
+        "name": var.name,
+        "value": if var.public { Some(&var.value) } else { None },
+        "public": var.public,
+    })
+}
+
+/// query parameters of a request
+struct Params(Vec<(String, String)>);
+
+impl Params {
+    fn new(query: &str) -> Self {
+        Params(
+            form_urlencoded::parse(query.trim_start_matches('?').as_bytes())
+                .into_owned()
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|pair| pair.0 == name).map(
+            |pair| pair.1.as_str(),
+        )
+    }
+
+    fn number(&self, name: &str) -> Option<usize> {
+        self.get(name).and_then(|value| value.parse().ok())
+    }
+
+    /// the href of `path` with this query, with `offset` replaced
+    fn href(&self, path: &str, offset: usize) -> String {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for pair in self.0.iter().filter(|pair| pair.0 != "offset") {
+            query.append_pair(&pair.0, &pair.1);
+        }
+        query.append_pair("offset", &offset.to_string());
+        format!("{}?{}", path, query.finish())
+    }
+}
+
+/// a page of a collection with `@pagination` metadata
+fn page(kind: &str, path: &str, params: &Params, items: Vec<Value>) -> Value {
+    let count = items.len();
+    let limit = params.number("limit").unwrap_or(DEFAULT_LIMIT).max(1);
+    let offset = params.number("offset").unwrap_or(0);
+    let link = |offset: usize| {
+        json!({
+            "@href": params.href(path, offset),
+            "offset": offset,
+            "limit": limit,
+        })
+    };
+    let last = count.saturating_sub(1) / limit * limit;
+    let next = if offset + limit < count {
+        link(offset + limit)
+    } else {
+        Value::Null
+    };
+    let prev = if offset > 0 {
+        link(offset.saturating_sub(limit))
+    } else {
+        Value::Null
+    };
+    json!({
+        "@type": kind,
+        "@href": params.href(path, offset),
+        "@representation": "standard",
+        "@pagination": {
+            "limit": limit,
+            "offset": offset,
+            "count": count,
+            "is_first": offset == 0,
+            "is_last": offset + limit >= count,
+            "next": next,
+            "prev": prev,
+            "first": link(0),
+            "last": link(last),
+        },
+        kind: items.into_iter().skip(offset).take(limit).collect::<Vec<_>>(),
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn duration(build: &Build) -> Option<u64> {
+    match (build.started_at, build.finished_at) {
+        (Some(started), Some(finished)) => Some(finished - started),
+        _ => None,
+    }
+}
+
+/// formats seconds since the unix epoch as an ISO-8601 timestamp
+fn timestamp(secs: u64) -> String {
+    // days to a civil date, per
+    // http://howardhinnant.github.io/date_algorithms.html
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// serves requests with the state of the world
+struct Handler {
+    world: Arc<Mutex<World>>,
+}
+
+impl Service for Handler {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, request: Request) -> Self::Future {
+        let world = self.world.clone();
+        let method = request.method().clone();
+        let path = request.path().to_owned();
+        let query = request.query().unwrap_or_default().to_owned();
+        Box::new(request.body().concat2().map(move |body| {
+            let (status, value) = world.lock().unwrap().respond(
+                &method,
+                &path,
+                &query,
+                &body,
+            );
+            let response = Response::new().with_status(status);
+            if value.is_null() {
+                response
+            } else {
+                response.with_header(ContentType::json()).with_body(
+                    value.to_string(),
+                )
+            }
+        }))
+    }
+}
+
+/// A fake travis API server, stopped when dropped
+pub struct Server {
+    addr: SocketAddr,
+    world: Arc<Mutex<World>>,
+    _stop: oneshot::Sender<()>,
+}
+
+impl Server {
+    /// Starts a server on a local port on a background thread
+    pub fn start() -> io::Result<Self> {
+        let world = Arc::new(Mutex::new(World::default()));
+        let (stop, stopped) = oneshot::channel::<()>();
+        let (bound, addr) = mpsc::channel();
+        let shared = world.clone();
+        thread::spawn(move || {
+            let local = "127.0.0.1:0".parse().expect("invalid local address");
+            let listening = Core::new().and_then(|core| {
+                let listener = TcpListener::bind(&local, &core.handle())?;
+                Ok((core, listener))
+            });
+            let (mut core, listener) = match listening {
+                Ok(listening) => listening,
+                Err(err) => {
+                    let _ = bound.send(Err(err));
+                    return;
+                }
+            };
+            let handle = core.handle();
+            let _ = bound.send(listener.local_addr());
+            let http = Http::<Chunk>::new();
+            let serving = listener.incoming().for_each(move |(socket, _)| {
+                let connection = http.serve_connection(
+                    socket,
+                    Handler { world: shared.clone() },
+                );
+                handle.spawn(connection.map(|_| ()).map_err(|_| ()));
+                Ok(())
+            });
+            let _ = core.run(serving.map_err(|_| ()).select(
+                stopped.then(|_| Ok(())),
+            ));
+        });
+        let addr = addr.recv().map_err(|_| {
+            io::Error::new(io::ErrorKind::Other, "test server failed to start")
+        })??;
+        Ok(Server {
+            addr,
+            world,
+            _stop: stop,
+        })
+    }
+
+    /// The url to create clients with
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Registers a public repository, returning its id
+    ///
+    /// The repository's owner is registered along with it. Registering a
+    /// repository again returns its existing id.
+    pub fn repo<S>(&self, slug: S) -> usize
+    where
+        S: AsRef<str>,
+    {
+        self.world.lock().unwrap().repo(slug.as_ref())
+    }
+
+    /// Triggers a build of `branch`, returning its id
+    ///
+    /// Builds are created in the `Created` state with one job, as when
+    /// requested through the API. Returns `None` when the repository
+    /// isn't registered.
+    pub fn trigger<S, B>(&self, slug: S, branch: B) -> Option<usize>
+    where
+        S: AsRef<str>,
+        B: AsRef<str>,
+    {
+        self.world.lock().unwrap().trigger(
+            slug.as_ref(),
+            branch.as_ref(),
+            "push",
+        )
+    }
+
+    /// Moves a build and its job to `state` immediately
+    ///
+    /// Starting a build sets its `started_at`, finishing one its
+    /// `finished_at` and `duration`
+    pub fn transition(&self, build: usize, state: State) {
+        if let Some(build) = self.world.lock().unwrap().build(build) {
+            build.transition(state, now())
+        }
+    }
+
+    /// Queues states for a build to move through, one each time it or its
+    /// job is served
+    ///
+    /// This simulates progress observed by polling clients
+    pub fn script(&self, build: usize, states: Vec<State>) {
+        if let Some(build) = self.world.lock().unwrap().build(build) {
+            build.script.extend(states)
+        }
+    }
+
+    /// The state of a build, if it exists
+    pub fn state(&self, build: usize) -> Option<State> {
+        self.world.lock().unwrap().build(build).map(|build| {
+            build.state.clone()
+        })
+    }
+
+    /// The requests received so far (`METHOD /path?query`), in order
+    pub fn requests(&self) -> Vec<String> {
+        self.world.lock().unwrap().requests.clone()
+    }
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Server").field("addr", &self.addr).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Client, Credential};
+    use env::{EnvVarCreate, EnvVarPatch};
+    use error::{ApiError, Error, ErrorKind};
+    use hyper::Client as HyperClient;
+    use hyper::client::HttpConnector;
+    use pagination::PageOptions;
+
+    fn client(
+        server: &Server,
+        credential: Option<Credential>,
+        core: &mut Core,
+    ) -> Client<HttpConnector> {
+        let http = HyperClient::new(&core.handle());
+        Client::custom(server.url(), http, credential, core).unwrap()
+    }
+
+    #[test]
+    fn env_vars_are_stateful() {
+        let server = Server::start().unwrap();
+        server.repo("softprops/travis");
+        let mut core = Core::new().unwrap();
+        let travis = client(
+            &server,
+            Some(Credential::Github("gh-token".into())),
+            &mut core,
+        );
+        let env = travis.env("softprops/travis");
+        let created = core.run(env.set(EnvVarCreate {
+            name: "KEY".into(),
+            value: "s3cret".into(),
+            public: false,
+        })).unwrap();
+        assert_eq!(created.value, None);
+        match core.run(env.set(EnvVarCreate {
+            name: "KEY".into(),
+            value: "again".into(),
+            public: false,
+        })) {
+            Err(Error(ErrorKind::Api(ApiError::DuplicateResource(_)), _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+        let updated = core.run(env.update(
+            created.id.as_str(),
+            EnvVarPatch {
+                name: None,
+                value: Some("shared".into()),
+                public: Some(true),
+            },
+        )).unwrap();
+        assert_eq!(updated.value, Some("shared".into()));
+        assert_eq!(core.run(env.vars()).unwrap().len(), 1);
+        core.run(env.delete(created.id.as_str())).unwrap();
+        assert!(core.run(env.vars()).unwrap().is_empty());
+        assert_eq!(server.requests()[0], "POST /auth/github");
+    }
+
+    #[test]
+    fn builds_move_through_scripted_states() {
+        let server = Server::start().unwrap();
+        server.repo("softprops/travis");
+        let build = server.trigger("softprops/travis", "master").unwrap();
+        server.script(build, vec![State::Started, State::Passed]);
+        let mut core = Core::new().unwrap();
+        let travis = client(&server, None, &mut core);
+        let builds = travis.builds("softprops/travis");
+        let states = (0..3)
+            .map(|_| {
+                core.run(builds.list(&Default::default())).unwrap()[0]
+                    .state
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(states, vec![State::Started, State::Passed, State::Passed]);
+        let listed = core.run(builds.list(&Default::default())).unwrap();
+        assert!(listed[0].duration.is_some());
+        let jobs = core.run(travis.jobs(build).list()).unwrap();
+        assert_eq!(jobs[0].state, Some(State::Passed));
+        let embedded = listed[0].jobs[0].clone();
+        let job = core.run(travis.standard(embedded)).unwrap();
+        assert_eq!(job.number, Some("1.1".into()));
+        assert_eq!(job.state, Some(State::Passed));
+
+        let next = server.trigger("softprops/travis", "master").unwrap();
+        server.transition(next, State::Failed);
+        let listed = core.run(builds.list(&Default::default())).unwrap();
+        assert_eq!(listed[0].id, next);
+        assert_eq!(listed[0].state, State::Failed);
+        assert_eq!(listed[0].previous_state, Some(State::Passed));
+    }
+
+    #[test]
+    fn collections_are_paginated() {
+        let server = Server::start().unwrap();
+        for name in &["a", "b", "c"] {
+            server.repo(format!("softprops/{}", name));
+        }
+        let mut core = Core::new().unwrap();
+        let travis = client(&server, None, &mut core);
+        let paging = PageOptions::builder().page_size(2).build().unwrap();
+        let repos = core.run(
+            travis
+                .repos()
+                .iter_with("softprops", &Default::default(), &paging)
+                .collect(),
+        ).unwrap();
+        assert_eq!(
+            repos.iter().map(|repo| repo.slug.as_str()).collect::<Vec<_>>(),
+            vec!["softprops/a", "softprops/b", "softprops/c"]
+        );
+        assert_eq!(
+            server
+                .requests()
+                .iter()
+                .filter(|request| request.starts_with("GET /owner"))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn unknown_resources_are_not_found() {
+        let server = Server::start().unwrap();
+        let mut core = Core::new().unwrap();
+        let travis = client(&server, None, &mut core);
+        match core.run(travis.env("softprops/missing").vars()) {
+            Err(Error(ErrorKind::Api(ApiError::NotFound(ref fault)), _)) => {
+                assert_eq!(fault.resource_type, Some("repository".into()))
+            }
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+        assert_eq!(server.trigger("softprops/missing", "master"), None);
+    }
+
+    #[test]
+    fn requests_trigger_builds() {
+        let server = Server::start().unwrap();
+        server.repo("softprops/travis");
+        let mut core = Core::new().unwrap();
+        let http = HyperClient::new(&core.handle());
+        let mut request = hyper::Request::new(
+            Method::Post,
+            format!("{}/repo/softprops%2Ftravis/requests", server.url())
+                .parse()
+                .unwrap(),
+        );
+        request.set_body(r#"{"request": {"branch": "develop"}}"#);
+        let response = core.run(http.request(request)).unwrap();
+        assert_eq!(response.status(), StatusCode::Accepted);
+        let travis = client(&server, None, &mut core);
+        let builds = core.run(
+            travis.builds("softprops/travis").list(&Default::default()),
+        ).unwrap();
+        assert_eq!(builds.len(), 1);
+        assert_eq!(builds[0].state, State::Created);
+        assert_eq!(builds[0].branch.name, "develop");
+        assert_eq!(builds[0].event_type, "api");
+    }
+
+    #[test]
+    fn actions_are_published() {
+        let server = Server::start().unwrap();
+        server.repo("softprops/travis");
+        server.trigger("softprops/travis", "master").unwrap();
+        let mut core = Core::new().unwrap();
+        let travis = client(&server, None, &mut core);
+        let builds = core.run(travis.action::<Value>(
+            "builds",
+            "find",
+            &[("repository.slug", "softprops/travis"), ("limit", "1")],
+        )).unwrap();
+        assert_eq!(builds["builds"][0]["state"], "created");
+        assert_eq!(
+            server.requests(),
+            vec!["GET /", "GET /repo/softprops%2Ftravis/builds?limit=1"]
+        );
+    }
+
+    #[test]
+    fn timestamps_are_iso_8601() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(1_520_000_000), "2018-03-02T14:13:20Z");
+    }
+}
@@ -0,0 +1,192 @@
+//! Helpers for exercising clients against canned responses
+
+use {Client, Credential};
+use hyper::Client as HyperClient;
+use hyper::client::HttpConnector;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio_core::reactor::Core;
+use transport::{NoConnector, Transport};
+
+/// A canned http response
+pub struct Canned {
+    pub status: u16,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+    pub delay: Option<Duration>,
+}
+
+impl Canned {
+    pub fn new<B>(status: u16, body: B) -> Self
+    where
+        B: Into<String>,
+    {
+        Canned {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            delay: None,
+        }
+    }
+
+    pub fn header<V>(mut self, name: &'static str, value: V) -> Self
+    where
+        V: Into<String>,
+    {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// waits for `delay` before responding
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+/// Serves one canned response per connection, in order, on a local port
+///
+/// Resolves to the server's address along with the request lines
+/// (`METHOD /path`) it received
+pub fn serve(responses: Vec<Canned>) -> (String, Arc<Mutex<Vec<String>>>) {
+    listen(responses, false)
+}
+
+/// Like `serve`, recording the request line and headers of each request
+pub fn serve_heads(
+    responses: Vec<Canned>,
+) -> (String, Arc<Mutex<Vec<String>>>) {
+    listen(responses, true)
+}
+
+fn listen(
+    responses: Vec<Canned>,
+    heads: bool,
+) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    thread::spawn(move || for canned in responses {
+        let (stream, _) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(_) => return,
+        };
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut head = if heads {
+            line.clone()
+        } else {
+            line.split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+        };
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header.trim().is_empty() {
+                break;
+            }
+            if heads {
+                head.push_str(&header);
+            }
+            let lower = header.to_lowercase();
+            if lower.starts_with("content-length:") {
+                length = lower[15..].trim().parse().unwrap();
+            }
+        }
+        received.lock().unwrap().push(head);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        if let Some(delay) = canned.delay {
+            thread::sleep(delay);
+        }
+        let mut response = format!(
+            "HTTP/1.1 {} Canned\r\nContent-Length: {}\r\nConnection: close\r\n",
+            canned.status,
+            canned.body.len()
+        );
+        for (name, value) in canned.headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("\r\n");
+        response.push_str(&canned.body);
+        let mut stream = reader.into_inner();
+        let _ = stream.write_all(response.as_bytes());
+    });
+    (format!("http://{}", addr), requests)
+}
+
+/// A stand-in http proxy which accepts `CONNECT` requests, relaying each
+/// tunnel's bytes to its target
+///
+/// Resolves to the proxy's `host:port` along with the request heads of the
+/// `CONNECT` requests it received
+pub fn tunnel_proxy() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let tunnels = Arc::new(Mutex::new(Vec::new()));
+    let received = tunnels.clone();
+    thread::spawn(move || for stream in listener.incoming() {
+        let mut reader = BufReader::new(stream.unwrap());
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            head.push_str(&line);
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+        let target = head.split_whitespace().nth(1).unwrap().to_owned();
+        received.lock().unwrap().push(head);
+        let mut client = reader.into_inner();
+        let server = TcpStream::connect(target.as_str()).unwrap();
+        client
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+            .unwrap();
+        let relay = |mut from: TcpStream, mut to: TcpStream| {
+            thread::spawn(move || {
+                let _ = ::std::io::copy(&mut from, &mut to);
+                let _ = to.shutdown(Shutdown::Write);
+            })
+        };
+        relay(client.try_clone().unwrap(), server.try_clone().unwrap());
+        relay(server, client);
+    });
+    (addr.to_string(), tunnels)
+}
+
+/// A plain http client for a `serve`d host
+pub fn client(
+    host: &str,
+    core: &mut Core,
+) -> Client<HttpConnector> {
+    let http = HyperClient::configure().keep_alive(false).build(
+        &core.handle(),
+    );
+    Client::custom(
+        host,
+        http,
+        Some(Credential::Token("test-token".into())),
+        core,
+    ).unwrap()
+}
+
+/// An unauthenticated client for the open source host, sending requests
+/// with `transport`
+pub fn transported<T>(transport: T, core: &mut Core) -> Client<NoConnector>
+where
+    T: Transport + 'static,
+{
+    let client = Client::from_transport(
+        ::OSS_HOST,
+        transport,
+        &core.handle(),
+        None,
+    );
+    core.run(client).unwrap()
+}
@@ -0,0 +1,322 @@
+//! Recording travis interactions and replaying them offline
+//!
+//! A `Recorder` wraps a transport, writing each request's method, url
+//! and headers along with its response to a JSON cassette. A `Replay`
+//! transport serves a cassette's responses back, failing requests which
+//! weren't recorded.
+//!
+//! Credentials are redacted before cassettes are written: `Authorization`
+//! headers, tokens and env var values are replaced with `[redacted]`.
+
+use {Error, Future};
+use error::ErrorKind;
+use futures::{Future as StdFuture, future};
+use hyper::StatusCode;
+use hyper::header::Headers;
+use logging::{self, REDACTED};
+use serde_json;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use super::{Request, Response, Transport};
+
+/// A recorded request and the response it received
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+/// An ordered collection of interactions
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Reads a cassette from a JSON file
+    pub fn load<P>(path: P) -> ::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    /// Writes this cassette to a JSON file
+    pub fn save<P>(&self, path: P) -> ::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+}
+
+fn headers(headers: &Headers) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|header| {
+            let value = if logging::secret_header(header.name()) {
+                REDACTED.to_owned()
+            } else {
+                header.value_string()
+            };
+            (header.name().to_owned(), value)
+        })
+        .collect()
+}
+
+fn body(body: &[u8]) -> String {
+    logging::redact_body(body, false)
+}
+
+/// Records interactions with the transport it wraps to a cassette file,
+/// which is rewritten after each response
+///
+/// Recorders are usually installed as a layer
+///
+/// ```no_run
+/// # extern crate hyper;
+/// # extern crate travis;
+/// # use hyper::client::Connect;
+/// # use std::rc::Rc;
+/// # use travis::Client;
+/// # use travis::transport::Transport;
+/// use travis::transport::cassette::Recorder;
+///
+/// # fn record<C: Clone + Connect>(travis: Client<C>) -> Client<C> {
+/// travis.with_layer(|inner| -> Rc<Transport> {
+///     Rc::new(Recorder::new(inner, "tests/fixtures/env.json"))
+/// })
+/// # }
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    inner: Rc<Transport>,
+    path: PathBuf,
+    cassette: Rc<RefCell<Cassette>>,
+}
+
+impl Recorder {
+    pub fn new<P>(inner: Rc<Transport>, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Recorder {
+            inner,
+            path: path.into(),
+            cassette: Default::default(),
+        }
+    }
+
+    /// The interactions recorded so far
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.borrow().clone()
+    }
+}
+
+impl Transport for Recorder {
+    fn send(&self, request: Request) -> Future<Response> {
+        let recorded = RecordedRequest {
+            method: request.method.to_string(),
+            url: request.url.clone(),
+            headers: headers(&request.headers),
+            body: request.body.as_ref().map(|b| body(b)),
+        };
+        let cassette = self.cassette.clone();
+        let path = self.path.clone();
+        Box::new(self.inner.send(request).and_then(move |response| {
+            let mut cassette = cassette.borrow_mut();
+            cassette.interactions.push(Interaction {
+                request: recorded,
+                response: RecordedResponse {
+                    status: response.status.as_u16(),
+                    headers: headers(&response.headers),
+                    body: body(&response.body),
+                },
+            });
+            cassette.save(&path)?;
+            Ok(response)
+        }))
+    }
+}
+
+/// Serves a cassette's recorded responses
+///
+/// Each request is answered by the first interaction not yet replayed
+/// with the same method and url. Requests without one fail with
+/// `ErrorKind::Unmatched`.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    remaining: Rc<RefCell<Vec<Interaction>>>,
+}
+
+impl Replay {
+    pub fn new(cassette: Cassette) -> Self {
+        Replay { remaining: Rc::new(RefCell::new(cassette.interactions)) }
+    }
+
+    /// Replays the cassette stored at `path`
+    pub fn load<P>(path: P) -> ::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Cassette::load(path).map(Replay::new)
+    }
+
+    /// Interactions which haven't been replayed yet
+    pub fn remaining(&self) -> Vec<Interaction> {
+        self.remaining.borrow().clone()
+    }
+}
+
+impl Transport for Replay {
+    fn send(&self, request: Request) -> Future<Response> {
+        let mut remaining = self.remaining.borrow_mut();
+        let method = request.method.to_string();
+        let position = remaining.iter().position(|interaction| {
+            interaction.request.method == method &&
+                interaction.request.url == request.url
+        });
+        let interaction = match position {
+            Some(position) => remaining.remove(position),
+            None => {
+                return Box::new(future::err(
+                    ErrorKind::Unmatched(method, request.url).into(),
+                ))
+            }
+        };
+        let RecordedResponse {
+            status,
+            headers: recorded,
+            body,
+        } = interaction.response;
+        let mut headers = Headers::new();
+        for (name, value) in recorded {
+            headers.set_raw(name, value);
+        }
+        Box::new(future::result(
+            StatusCode::try_from(status)
+                .map_err(|_| Error::from(format!("invalid status {}", status)))
+                .map(|status| {
+                    Response {
+                        status,
+                        headers,
+                        body: body.into_bytes(),
+                    }
+                }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Client, Credential};
+    use env::EnvVarCreate;
+    use hyper::Method;
+    use std::env;
+    use std::fs;
+    use std::process;
+    use super::super::Memory;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn recorded_secrets_are_redacted() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(
+            Method::Post,
+            "/repo/foo%2Fbar/env_vars",
+            201,
+            r#"{"@type": "env_var", "id": "1", "name": "KEY",
+            "value": "s3cret", "@permissions": {"read": true, "write": true}}"#,
+        );
+        let path = env::temp_dir().join(format!(
+            "travis-recorded-secrets-{}.json",
+            process::id()
+        ));
+        let recorded = path.clone();
+        let travis = core.run(Client::from_transport(
+            "https://api.travis-ci.org",
+            memory,
+            &core.handle(),
+            Some(Credential::Token("t0ken".into())),
+        )).unwrap()
+            .with_layer(move |inner| -> Rc<Transport> {
+                Rc::new(Recorder::new(inner, recorded.clone()))
+            });
+        core.run(travis.env("foo/bar").set(EnvVarCreate {
+            name: "KEY".into(),
+            value: "s3cret".into(),
+            public: false,
+        })).unwrap();
+        let cassette = Cassette::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let written = serde_json::to_string(&cassette).unwrap();
+        assert!(!written.contains("s3cret"));
+        assert!(!written.contains("t0ken"));
+        let request = &cassette.interactions[0].request;
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.headers["Authorization"], REDACTED);
+        assert_eq!(cassette.interactions[0].response.status, 201);
+    }
+
+    #[test]
+    fn interactions_are_replayed_once() {
+        let mut core = Core::new().unwrap();
+        let url = "https://api.travis-ci.org/build/1/jobs";
+        let replay = Replay::new(Cassette {
+            interactions: vec![
+                Interaction {
+                    request: RecordedRequest {
+                        method: "GET".into(),
+                        url: url.into(),
+                        headers: BTreeMap::new(),
+                        body: None,
+                    },
+                    response: RecordedResponse {
+                        status: 200,
+                        headers: BTreeMap::new(),
+                        body: r#"{"jobs": []}"#.into(),
+                    },
+                },
+            ],
+        });
+        let request = || {
+            Request {
+                method: Method::Get,
+                url: url.into(),
+                headers: Headers::new(),
+                body: None,
+            }
+        };
+        let response = core.run(replay.send(request())).unwrap();
+        assert_eq!(response.status, StatusCode::Ok);
+        assert!(replay.remaining().is_empty());
+        match core.run(replay.send(request())) {
+            Err(Error(ErrorKind::Unmatched(..), _)) => (),
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+    }
+}
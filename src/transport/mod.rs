@@ -0,0 +1,366 @@
+//! Transports carry requests to travis and responses back
+//!
+//! Clients send every request through a `Transport`, by default a hyper
+//! client. Other transports may stand in for travis, like the in-memory
+//! `Memory` transport for tests or the `cassette` module's `Replay`, and
+//! `Layer`s wrap transports with middleware like metrics or caching.
+
+use {Error, Future};
+use futures::{Future as StdFuture, IntoFuture, Stream as StdStream, future};
+use hyper::{Client as HyperClient, Method, StatusCode, Uri};
+use hyper::client::{Connect, Service};
+use hyper::header::Headers;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+use tokio_core::net::TcpStream;
+
+pub mod cassette;
+
+/// A request to send to travis
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    /// the absolute url, including the travis host
+    pub url: String,
+    pub headers: Headers,
+    /// the json encoded body, if any
+    pub body: Option<Vec<u8>>,
+}
+
+impl Request {
+    /// the request url's path, without its query
+    pub fn path(&self) -> &str {
+        let path = self.url
+            .find("://")
+            .and_then(|scheme| {
+                self.url[scheme + 3..].find('/').map(|at| scheme + 3 + at)
+            })
+            .map_or("/", |at| &self.url[at..]);
+        path.split('?').next().unwrap_or(path)
+    }
+}
+
+/// A response received from travis, whatever its status
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates a response with `status`, `body` and no headers
+    pub fn new<B>(status: StatusCode, body: B) -> Self
+    where
+        B: Into<Vec<u8>>,
+    {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: body.into(),
+        }
+    }
+}
+
+/// Sends requests, resolving to their responses regardless of status
+///
+/// Transports fail only when no response was received, like when a
+/// connection is refused.
+pub trait Transport {
+    /// Sends `request`, resolving to the response travis returned
+    fn send(&self, request: Request) -> Future<Response>;
+}
+
+impl<C> Transport for HyperClient<C>
+where
+    C: Clone + Connect,
+{
+    fn send(&self, request: Request) -> Future<Response> {
+        let http = self.clone();
+        let uri = request.url.parse::<Uri>().map_err(Error::from);
+        Box::new(uri.into_future().and_then(move |uri| {
+            let mut req = ::hyper::Request::new(request.method, uri);
+            *req.headers_mut() = request.headers;
+            if let Some(body) = request.body {
+                req.set_body(body);
+            }
+            http.request(req).map_err(Error::from).and_then(|response| {
+                let status = response.status();
+                let headers = response.headers().clone();
+                response.body().concat2().map_err(Error::from).map(
+                    move |body| {
+                        Response {
+                            status,
+                            headers,
+                            body: body.to_vec(),
+                        }
+                    },
+                )
+            })
+        }))
+    }
+}
+
+/// Wraps transports with middleware. See `Client::with_layer`
+///
+/// Layers are implemented for closures which wrap a transport
+pub trait Layer {
+    /// Wraps `inner`, returning the transport requests are sent with
+    fn layer(&self, inner: Rc<Transport>) -> Rc<Transport>;
+}
+
+impl<F> Layer for F
+where
+    F: Fn(Rc<Transport>) -> Rc<Transport>,
+{
+    fn layer(&self, inner: Rc<Transport>) -> Rc<Transport> {
+        self(inner)
+    }
+}
+
+/// The connector type of clients created with `Client::from_transport`
+///
+/// Those clients send requests with their transport rather than a hyper
+/// client, so this connector fails any connection asked of it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoConnector;
+
+impl Service for NoConnector {
+    type Request = Uri;
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = future::FutureResult<TcpStream, io::Error>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        future::err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("no connector to connect to {} with", uri),
+        ))
+    }
+}
+
+#[derive(Default)]
+struct Routes {
+    routes: Vec<(Method, String, VecDeque<Response>)>,
+    requests: Vec<Request>,
+}
+
+/// An in-memory transport serving canned responses by method and path
+///
+/// Responses registered for the same route are served in order, the last
+/// being repeated. Requests without a route receive a travis `not_found`
+/// error. Clones share routes.
+///
+/// ```no_run
+/// extern crate hyper;
+/// extern crate tokio_core;
+/// extern crate travis;
+///
+/// use hyper::Method;
+/// use tokio_core::reactor::Core;
+/// use travis::Client;
+/// use travis::transport::Memory;
+///
+/// fn main() {
+///   let mut core = Core::new().unwrap();
+///   let memory = Memory::new();
+///   memory.respond(
+///     Method::Get,
+///     "/repo/foo%2Fbar/env_vars",
+///     200,
+///     r#"{"env_vars": []}"#,
+///   );
+///   let travis = core.run(Client::from_transport(
+///     "https://api.travis-ci.org",
+///     memory.clone(),
+///     &core.handle(),
+///     None,
+///   )).unwrap();
+///   let vars = core.run(travis.env("foo/bar").vars()).unwrap();
+///   assert!(vars.is_empty());
+///   assert_eq!(memory.requests().len(), 1);
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Memory {
+    routes: Rc<RefCell<Routes>>,
+}
+
+impl Memory {
+    /// Creates a transport with no routes
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Serves `body` with `status` to `method` requests for `path`
+    ///
+    /// Paths including a query only match requests with that query, while
+    /// paths without one match any query. Routes are matched in the order
+    /// they were registered, so register routes with queries before the
+    /// same path without one.
+    pub fn respond<P, B>(&self, method: Method, path: P, status: u16, body: B)
+    where
+        P: Into<String>,
+        B: Into<Vec<u8>>,
+    {
+        let status = StatusCode::try_from(status).expect("invalid status");
+        self.respond_with(method, path, Response::new(status, body))
+    }
+
+    /// Serves `response` to `method` requests for `path`
+    pub fn respond_with<P>(&self, method: Method, path: P, response: Response)
+    where
+        P: Into<String>,
+    {
+        let path = path.into();
+        let mut routes = self.routes.borrow_mut();
+        let existing = routes.routes.iter().position(|route| {
+            route.0 == method && route.1 == path
+        });
+        match existing {
+            Some(at) => routes.routes[at].2.push_back(response),
+            None => {
+                routes.routes.push(
+                    (method, path, vec![response].into_iter().collect()),
+                )
+            }
+        }
+    }
+
+    /// The requests received so far, in order
+    pub fn requests(&self) -> Vec<Request> {
+        self.routes.borrow().requests.clone()
+    }
+}
+
+impl Transport for Memory {
+    fn send(&self, request: Request) -> Future<Response> {
+        let mut routes = self.routes.borrow_mut();
+        routes.requests.push(request.clone());
+        let path = request.path();
+        let full = &request.url[request.url.find(path).unwrap_or(0)..];
+        let route = routes.routes.iter_mut().find(|route| {
+            route.0 == request.method && (route.1 == path || route.1 == full)
+        });
+        let response = match route {
+            Some(&mut (_, _, ref mut responses)) => {
+                if responses.len() > 1 {
+                    responses.pop_front()
+                } else {
+                    responses.front().cloned()
+                }
+            }
+            None => None,
+        };
+        Box::new(future::ok(response.unwrap_or_else(|| {
+            Response::new(
+                StatusCode::NotFound,
+                format!(
+                    r#"{{"@type": "error", "error_type": "not_found",
+                    "error_message": "no canned response for {} {}"}}"#,
+                    request.method,
+                    path
+                ),
+            )
+        })))
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Memory")
+            .field("routes", &self.routes.borrow().routes.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::{ApiError, ErrorKind};
+    use std::cell::Cell;
+    use testing::transported;
+    use tokio_core::reactor::Core;
+
+    const HOST: &str = "https://api.travis-ci.org";
+
+    #[test]
+    fn paths_exclude_queries() {
+        let request = Request {
+            method: Method::Get,
+            url: format!("{}/repo/foo%2Fbar/builds?limit=25", HOST),
+            headers: Headers::new(),
+            body: None,
+        };
+        assert_eq!(request.path(), "/repo/foo%2Fbar/builds");
+    }
+
+    #[test]
+    fn canned_responses_are_served_in_order() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(
+            Method::Get,
+            "/repo/foo%2Fbar/env_vars",
+            503,
+            "unavailable",
+        );
+        memory.respond(
+            Method::Get,
+            "/repo/foo%2Fbar/env_vars",
+            200,
+            r#"{"env_vars": []}"#,
+        );
+        let travis = transported(memory.clone(), &mut core);
+        assert!(core.run(travis.env("foo/bar").vars()).is_err());
+        assert!(core.run(travis.env("foo/bar").vars()).unwrap().is_empty());
+        assert!(core.run(travis.env("foo/bar").vars()).unwrap().is_empty());
+        assert_eq!(memory.requests().len(), 3);
+    }
+
+    #[test]
+    fn unmatched_requests_are_not_found() {
+        let mut core = Core::new().unwrap();
+        let travis = transported(Memory::new(), &mut core);
+        match core.run(travis.jobs(1).list()) {
+            Err(Error(ErrorKind::Api(ApiError::NotFound(ref fault)), _)) => {
+                assert!(fault.message.contains("GET /build/1/jobs"))
+            }
+            otherwise => panic!("unexpected {:?}", otherwise.map(|_| ())),
+        }
+    }
+
+    struct Counting {
+        inner: Rc<Transport>,
+        sent: Rc<Cell<usize>>,
+    }
+
+    impl Transport for Counting {
+        fn send(&self, request: Request) -> Future<Response> {
+            self.sent.set(self.sent.get() + 1);
+            self.inner.send(request)
+        }
+    }
+
+    #[test]
+    fn layers_wrap_transports() {
+        let mut core = Core::new().unwrap();
+        let memory = Memory::new();
+        memory.respond(Method::Get, "/build/1/jobs", 200, r#"{"jobs": []}"#);
+        let sent = Rc::new(Cell::new(0));
+        let counted = sent.clone();
+        let travis = transported(memory, &mut core)
+            .with_layer(move |inner| -> Rc<Transport> {
+                Rc::new(Counting {
+                    inner,
+                    sent: counted.clone(),
+                })
+            });
+        core.run(travis.jobs(1).list()).unwrap();
+        core.run(travis.jobs(1).list()).unwrap();
+        assert_eq!(sent.get(), 2);
+    }
+}